@@ -1,19 +1,44 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::process;
 use std::thread;
 use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::{anyhow, Result};
+use anyhow::{bail, Result};
 use once_cell::sync::Lazy;
 use powerpack::detach;
 use powerpack::env;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json as json;
 
+#[cfg(feature = "sqlite")]
+use crate::config::{Backend, CONFIG};
 use crate::logger;
+#[cfg(feature = "sqlite")]
+use crate::sqlite;
 
-const UPDATE_INTERVAL: Duration = Duration::from_secs(15);
+/// How often the data is fetched from scratch instead of only fetching what
+/// changed since the last update.
+const RESYNC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How long an update can fetch pages for before it gives up.
+const UPDATE_DEADLINE: Duration = Duration::from_secs(10 * 60);
+
+/// How long an update can hold the lock on an entry before it is considered
+/// stuck and the lock is reclaimed. Updates check their deadline between
+/// pages, so one that holds the lock for much longer is stuck in a request.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(20 * 60);
+
+/// How often entries that are no longer needed are removed.
+const GC_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The most disk space the cache entries can use, including their items in the
+/// SQLite database.
+const MAX_SIZE: u64 = 100 * 1024 * 1024;
 
 pub static DIR: Lazy<PathBuf> = Lazy::new(|| {
     env::workflow_cache().unwrap_or_else(|| {
@@ -26,84 +51,742 @@ pub static DIR: Lazy<PathBuf> = Lazy::new(|| {
     })
 });
 
+/// The version of the cache format, increased whenever the format of the
+/// data changes.
+const VERSION: u32 = 3;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct Cache {
+    /// The version of the format, entries written before it was versioned
+    /// are version 0.
+    #[serde(default)]
+    version: u32,
+    /// A readable description of the entry, missing in version 0.
+    #[serde(default)]
+    key: String,
     checksum: [u8; 20],
+    /// The last time the data was updated.
     modified: SystemTime,
+    /// The last time the data was fetched from scratch, missing in version 0.
+    #[serde(default = "unix_epoch")]
+    synced: SystemTime,
+    /// Set while the data is still being fetched for the first time.
+    #[serde(default)]
+    progress: Option<Progress>,
     data: json::Value,
 }
 
-pub fn load<F>(key: &str, checksum: [u8; 20], f: F) -> Result<json::Value>
+/// How far along fetching data for the first time is.
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+pub struct Progress {
+    /// The number of pages fetched so far.
+    pub pages: usize,
+    /// The total number of pages to fetch, if known.
+    pub total: Option<usize>,
+}
+
+/// The last error that occurred while updating the cache.
+#[derive(Debug, Deserialize, Serialize)]
+struct Failure<E> {
+    time: SystemTime,
+    /// A readable description of the entry, in case there is no data.
+    #[serde(default)]
+    key: Option<String>,
+    error: E,
+}
+
+/// The process that holds the lock on a cache entry, recorded while it holds
+/// it.
+///
+/// The lock itself is released by the OS when the process exits, this is used
+/// to tell whether an update is in flight without taking the lock, and to
+/// reclaim the lock from an update that is stuck.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct Holder {
+    pub pid: u32,
+    pub started: SystemTime,
+}
+
+/// A description of a cache entry, used to list them.
+#[derive(Debug)]
+pub struct Entry {
+    pub id: String,
+    pub key: Option<String>,
+    /// The disk space used by the entry in bytes, including its share of the
+    /// SQLite database.
+    pub size: u64,
+    /// The last time the data was updated, if there is any.
+    pub modified: Option<SystemTime>,
+    /// The error from the last update, if it failed.
+    pub error: Option<String>,
+    /// The process that is updating the entry, if any.
+    pub updating: Option<Holder>,
+}
+
+/// Data loaded from the cache.
+#[derive(Debug)]
+pub struct Loaded<T, E> {
+    pub data: T,
+    /// The last time the data was updated.
+    pub modified: SystemTime,
+    /// The error from the last update, if it failed.
+    pub error: Option<E>,
+    /// Set if the data is only partially fetched, in which case the data is
+    /// `null` if not even the first page has been fetched.
+    pub progress: Option<Progress>,
+}
+
+/// A cache entry that is locked for updating.
+pub struct Update {
+    key: String,
+    dir: PathBuf,
+    path: PathBuf,
+    checksum: [u8; 20],
+    curr: Option<Cache>,
+    /// Whether the entry had complete data when it was locked.
+    complete: bool,
+    /// When the data was last fetched from scratch, set if the previous data
+    /// was taken to be refreshed incrementally.
+    synced: Option<SystemTime>,
+    /// When the lock was taken.
+    started: SystemTime,
+    _guard: fmutex::Guard,
+}
+
+/// Loads the cached data for the given key.
+///
+/// Entries are stored in a directory named after the checksum, which must
+/// identify everything the data depends on, the key is only used to describe
+/// the entry.
+///
+/// If the data is older than the given interval then the given function is
+/// called in a detached process to update it, see [`begin`]. An interval of
+/// `None` only updates the data if there is none or it is partial. If the
+/// function fails while the cache is being populated for the first time then
+/// the error it recorded is returned, otherwise the stale data is returned
+/// along with the error. If the data is still being fetched for the first
+/// time then the partial data fetched so far is returned along with its
+/// progress.
+pub fn load<F, E>(
+    key: &str,
+    checksum: [u8; 20],
+    interval: Option<Duration>,
+    f: F,
+) -> Result<Loaded<json::Value, E>>
 where
-    F: FnOnce() -> Result<json::Value>,
+    F: FnOnce() -> Result<()>,
+    E: DeserializeOwned + std::error::Error + Send + Sync + 'static,
 {
-    let dir = DIR.join(key);
+    let started = SystemTime::now();
+    let dir = entry_dir(checksum);
+    if let Some(curr) = update_if_due(key, &dir, checksum, interval, f)? {
+        return Ok(loaded(&dir, curr));
+    }
+
+    // wait up to 2 seconds for the update that is in progress to fetch the
+    // first page, after that the caller shows that the data is loading and
+    // tries again later
     let path = dir.join("data.json");
+    let start = Instant::now();
+    let poll_duration = Duration::from_secs(2);
+    while Instant::now().duration_since(start) < poll_duration {
+        thread::sleep(Duration::from_millis(200));
+        if let Some(curr) = read(&path, checksum)? {
+            return Ok(Loaded {
+                data: curr.data,
+                modified: curr.modified,
+                error: None,
+                progress: curr.progress,
+            });
+        }
+        if let Some(failure) = read_failure::<E>(&dir) {
+            if failure.time >= started {
+                return Err(failure.error.into());
+            }
+        }
+    }
+    Ok(Loaded {
+        data: json::Value::Null,
+        modified: started,
+        error: None,
+        progress: Some(Progress::default()),
+    })
+}
+
+/// Like [`load`] but returns `None` straight away if there is no data yet,
+/// instead of waiting for the first page to be fetched. If there is no data
+/// because the last update failed then its error is returned while the update
+/// is retried.
+pub fn try_load<F, E>(
+    key: &str,
+    checksum: [u8; 20],
+    interval: Option<Duration>,
+    f: F,
+) -> Result<Option<Loaded<json::Value, E>>>
+where
+    F: FnOnce() -> Result<()>,
+    E: DeserializeOwned + std::error::Error + Send + Sync + 'static,
+{
+    let dir = entry_dir(checksum);
+    match update_if_due(key, &dir, checksum, interval, f)? {
+        Some(curr) => Ok(Some(loaded(&dir, curr))),
+        None => match read_failure::<E>(&dir) {
+            Some(failure) => Err(failure.error.into()),
+            None => Ok(None),
+        },
+    }
+}
 
-    match fs::read(&path) {
-        Ok(data) => {
-            let curr: Cache = json::from_slice(&data)?;
-            let needs_update = curr.checksum != checksum || {
-                let now = SystemTime::now();
-                now.duration_since(curr.modified)? > UPDATE_INTERVAL
+/// Reads the cached data, calling the given function in a detached process to
+/// update it if it is missing or stale and isn't already being updated.
+fn update_if_due<F>(
+    key: &str,
+    dir: &Path,
+    checksum: [u8; 20],
+    interval: Option<Duration>,
+    f: F,
+) -> Result<Option<Cache>>
+where
+    F: FnOnce() -> Result<()>,
+{
+    let curr = read(&dir.join("data.json"), checksum)?;
+    if curr.as_ref().is_none_or(|curr| is_stale(curr, interval)) {
+        fs::create_dir_all(dir)?;
+        if !in_flight(dir) {
+            spawn(key, f)?;
+        }
+    }
+    Ok(curr)
+}
+
+/// Returns the cached data for the given checksum, if any, without ever
+/// updating it.
+pub fn cached<E: DeserializeOwned>(checksum: [u8; 20]) -> Result<Option<Loaded<json::Value, E>>> {
+    let dir = entry_dir(checksum);
+    let curr = read(&dir.join("data.json"), checksum)?;
+    Ok(curr.map(|curr| loaded(&dir, curr)))
+}
+
+fn loaded<E: DeserializeOwned>(dir: &Path, curr: Cache) -> Loaded<json::Value, E> {
+    // The error is removed when an update succeeds so it is always newer than
+    // the data.
+    let error = read_failure::<E>(dir)
+        .filter(|failure| failure.time >= curr.modified)
+        .map(|failure| failure.error);
+    Loaded {
+        data: curr.data,
+        modified: curr.modified,
+        error,
+        progress: curr.progress,
+    }
+}
+
+/// Whether the query has stayed the same for at least the given duration,
+/// which means that the user stopped typing it. Alfred reruns the workflow
+/// every second, so this becomes true shortly after they stop.
+pub fn settled(query: &str, after: Duration) -> Result<bool> {
+    #[derive(Deserialize, Serialize)]
+    struct Typed {
+        query: String,
+        since: SystemTime,
+    }
+
+    let path = DIR.join("query.json");
+    let prev = fs::read(&path)
+        .ok()
+        .and_then(|data| json::from_slice::<Typed>(&data).ok());
+    match prev {
+        Some(prev) if prev.query == query => {
+            Ok(prev.since.elapsed().is_ok_and(|elapsed| elapsed >= after))
+        }
+        _ => {
+            let typed = Typed {
+                query: query.to_owned(),
+                since: SystemTime::now(),
             };
+            fs::create_dir_all(&*DIR)?;
+            fs::write(&path, json::to_vec(&typed)?)?;
+            Ok(false)
+        }
+    }
+}
+
+/// Whether the cached data for the given checksum is missing, partial, or
+/// older than the given interval.
+pub fn is_due(checksum: [u8; 20], interval: Option<Duration>) -> bool {
+    match read(&entry_dir(checksum).join("data.json"), checksum) {
+        Ok(Some(curr)) => is_stale(&curr, interval),
+        Ok(None) => true,
+        Err(_) => false,
+    }
+}
 
-            if needs_update {
-                detach::spawn(|| match update(&dir, &path, checksum, f) {
-                    Ok(true) => log::info!("fetched {} and updated cache", path.display()),
-                    Ok(false) => {}
-                    Err(err) => log::error!("{:#}", err),
-                })?;
+/// Locks the cache entry for the given key so that it can be updated. Returns
+/// `None` if it is already being updated by another process, unless that
+/// process has held the lock for so long that it is considered stuck, in
+/// which case it is killed and the lock is reclaimed.
+pub fn begin(key: &str, checksum: [u8; 20]) -> Result<Option<Update>> {
+    let dir = entry_dir(checksum);
+    fs::create_dir_all(&dir)?;
+    let guard = match fmutex::try_lock(&dir)? {
+        Some(guard) => guard,
+        None => match Holder::read(&dir) {
+            Some(holder) if holder.is_stuck() && holder.is_alive() => {
+                log::warn!("reclaiming lock on {key} from stuck process {}", holder.pid);
+                holder.kill();
+                fmutex::lock(&dir)?
             }
+            _ => return Ok(None),
+        },
+    };
+    let holder = Holder {
+        pid: process::id(),
+        started: SystemTime::now(),
+    };
+    fs::write(dir.join("lock.json"), json::to_vec(&holder)?)?;
+    let path = dir.join("data.json");
+    // Partial data can't be refreshed incrementally.
+    let curr = read(&path, checksum)?.filter(|curr| curr.progress.is_none());
+    Ok(Some(Update {
+        key: key.to_owned(),
+        dir,
+        path,
+        checksum,
+        complete: curr.is_some(),
+        curr,
+        synced: None,
+        started: holder.started,
+        _guard: guard,
+    }))
+}
 
-            Ok(curr.data)
-        }
-        Err(err) if err.kind() == io::ErrorKind::NotFound => {
-            fs::create_dir_all(&dir)?;
-
-            detach::spawn(|| match update(&dir, &path, checksum, f) {
-                Ok(true) => log::info!("fetched {} and updated cache", path.display()),
-                Ok(false) => {}
-                Err(err) => log::error!("{:#}", err),
-            })?;
-
-            // wait up to 2 seconds for the cache to be populated
-            let start = Instant::now();
-            let poll_duration = Duration::from_secs(2);
-            while Instant::now().duration_since(start) < poll_duration {
-                thread::sleep(Duration::from_millis(200));
-                if let Ok(data) = fs::read(&path) {
-                    let curr: Cache = json::from_slice(&data)?;
-                    return Ok(curr.data);
-                }
+impl Update {
+    /// Takes the current data if it was fetched from scratch recently enough
+    /// that it can be refreshed incrementally.
+    pub fn take_previous(&mut self) -> Option<json::Value> {
+        let curr = self.curr.take()?;
+        let now = SystemTime::now();
+        match now.duration_since(curr.synced) {
+            Ok(d) if d < RESYNC_INTERVAL => {
+                self.synced = Some(curr.synced);
+                Some(curr.data)
             }
-            Err(anyhow!("timeout waiting for cached data"))
+            _ => None,
         }
-        Err(err) => Err(err.into()),
     }
-}
 
-fn update<F>(dir: &Path, path: &Path, checksum: [u8; 20], f: F) -> Result<bool>
-where
-    F: FnOnce() -> Result<json::Value>,
-{
-    logger::init()?;
-    let tmp = path.with_extension("tmp");
-    if let Some(_guard) = fmutex::try_lock(dir)? {
-        let data = f()?;
+    /// Returns the id of the entry, which identifies it in other stores.
+    #[cfg(feature = "sqlite")]
+    pub fn id(&self) -> String {
+        id(self.checksum)
+    }
+
+    /// Returns an error if the update has been fetching for longer than
+    /// [`UPDATE_DEADLINE`]. It is checked between pages so that an update with
+    /// many pages, or many retries, can't hold the lock forever.
+    pub fn check_deadline(&self) -> Result<()> {
+        let elapsed = self.started.elapsed().unwrap_or_default();
+        if elapsed > UPDATE_DEADLINE {
+            bail!(
+                "gave up updating {} after {} minutes",
+                self.key,
+                elapsed.as_secs() / 60
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether the entry had complete data when it was locked.
+    pub fn has_data(&self) -> bool {
+        self.complete
+    }
+
+    /// Writes the data fetched so far if the entry doesn't have any complete
+    /// data yet, so that it can be shown while the rest is fetched.
+    pub fn write_partial(&self, data: json::Value, progress: Progress) -> Result<()> {
+        if self.complete {
+            return Ok(());
+        }
+        self.write(data, Some(progress))
+    }
+
+    /// Writes the updated data and clears any previous error.
+    pub fn commit(self, data: json::Value) -> Result<()> {
+        self.write(data, None)?;
+        match fs::remove_file(self.dir.join("error.json")) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn write(&self, data: json::Value, progress: Option<Progress>) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
         let file = fs::File::create(&tmp)?;
         let modified = SystemTime::now();
         json::to_writer(
             &file,
             &Cache {
-                checksum,
+                version: VERSION,
+                key: self.key.clone(),
+                checksum: self.checksum,
                 modified,
+                synced: self.synced.unwrap_or(modified),
+                progress,
                 data,
             },
         )?;
-        fs::rename(tmp, path)?;
-        Ok(true)
-    } else {
-        Ok(false)
+        fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// Records the error that prevented the data from being updated.
+    pub fn fail<E: Serialize>(self, error: &E) -> Result<()> {
+        let failure = Failure {
+            time: SystemTime::now(),
+            key: Some(self.key.clone()),
+            error,
+        };
+        fs::write(self.dir.join("error.json"), json::to_vec(&failure)?)?;
+        Ok(())
+    }
+}
+
+impl Drop for Update {
+    fn drop(&mut self) {
+        // The lock itself is released after this when the guard is dropped.
+        let _ = fs::remove_file(self.dir.join("lock.json"));
+    }
+}
+
+impl Holder {
+    fn read(dir: &Path) -> Option<Self> {
+        let data = fs::read(dir.join("lock.json")).ok()?;
+        json::from_slice(&data).ok()
+    }
+
+    fn is_alive(&self) -> bool {
+        let pid = self.pid as libc::pid_t;
+        // Sending no signal only checks whether the process exists.
+        let ret = unsafe { libc::kill(pid, 0) };
+        ret == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+
+    fn is_stuck(&self) -> bool {
+        self.started.elapsed().is_ok_and(|d| d > LOCK_TIMEOUT)
+    }
+
+    fn kill(&self) {
+        unsafe { libc::kill(self.pid as libc::pid_t, libc::SIGKILL) };
+    }
+}
+
+/// Whether the cache entry is being updated by a process that isn't stuck.
+/// This only reads the record of the holder, taking the lock to check would
+/// make an update that starts at the same time think that it is taken.
+fn in_flight(dir: &Path) -> bool {
+    Holder::read(dir).is_some_and(|h| h.is_alive() && !h.is_stuck())
+}
+
+impl<T, E> Loaded<T, E> {
+    /// Converts the data, keeping its age and error.
+    pub fn try_map<U, F>(self, f: F) -> Result<Loaded<U, E>>
+    where
+        F: FnOnce(T) -> Result<U>,
+    {
+        Ok(Loaded {
+            data: f(self.data)?,
+            modified: self.modified,
+            error: self.error,
+            progress: self.progress,
+        })
+    }
+}
+
+fn spawn<F>(key: &str, f: F) -> Result<()>
+where
+    F: FnOnce() -> Result<()>,
+{
+    detach::spawn(|| {
+        if let Err(err) = logger::init() {
+            eprintln!("{err:#}");
+            return;
+        }
+        match f() {
+            Ok(()) => log::info!("updated cache for {key}"),
+            Err(err) => log::error!("{err:#}"),
+        }
+    })?;
+    Ok(())
+}
+
+/// Returns the id of the entry for the given checksum.
+#[cfg(feature = "sqlite")]
+pub fn id(checksum: [u8; 20]) -> String {
+    to_hex(checksum)
+}
+
+/// Returns every cache entry, most recently updated first.
+pub fn entries<E>() -> Result<Vec<Entry>>
+where
+    E: DeserializeOwned + Display,
+{
+    let mut entries = Vec::new();
+    let stored = stored_sizes();
+    for (id, dir) in entry_dirs()? {
+        let cache = fs::read(dir.join("data.json"))
+            .ok()
+            .and_then(|data| json::from_slice::<Cache>(&data).ok());
+        let failure = read_failure::<E>(&dir);
+        let updating = Holder::read(&dir).filter(Holder::is_alive);
+        let size = dir_size(&dir) + stored.get(&id).copied().unwrap_or_default();
+        entries.push(Entry {
+            id,
+            key: match &cache {
+                Some(cache) => Some(cache.key.clone()),
+                None => failure.as_ref().and_then(|f| f.key.clone()),
+            },
+            size,
+            modified: cache.map(|c| c.modified),
+            error: failure.map(|f| f.error.to_string()),
+            updating,
+        });
+    }
+    entries.sort_by_key(|e| std::cmp::Reverse(e.modified));
+    Ok(entries)
+}
+
+/// Removes the cache entry with the given id, failing if there is none.
+pub fn clear(id: &str) -> Result<()> {
+    match entry_dirs()?.into_iter().find(|(i, _)| i == id) {
+        Some((id, dir)) => remove(&id, &dir),
+        None => bail!("no cached data with id `{id}`"),
+    }
+}
+
+/// Removes every cache entry.
+pub fn clear_all() -> Result<()> {
+    for (_, dir) in entry_dirs()? {
+        fs::remove_dir_all(dir)?;
+    }
+    #[cfg(feature = "sqlite")]
+    if CONFIG.backend == Backend::Sqlite {
+        sqlite::clear()?;
+    }
+    Ok(())
+}
+
+/// Removes the cache entry along with its items in the SQLite database.
+#[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+fn remove(id: &str, dir: &Path) -> Result<()> {
+    fs::remove_dir_all(dir)?;
+    #[cfg(feature = "sqlite")]
+    if CONFIG.backend == Backend::Sqlite {
+        sqlite::remove(id)?;
+    }
+    Ok(())
+}
+
+/// Gives back the space used by removed entries in the SQLite database.
+fn compact() -> Result<()> {
+    #[cfg(feature = "sqlite")]
+    if CONFIG.backend == Backend::Sqlite {
+        sqlite::vacuum()?;
+    }
+    Ok(())
+}
+
+/// Removes the entries that are not referenced and the least recently
+/// updated entries over the size limit, in a detached process at most once a
+/// day.
+pub fn gc(referenced: HashSet<[u8; 20]>) -> Result<()> {
+    let path = DIR.join("gc");
+    let due = fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .map_or(true, |t| t.elapsed().map_or(true, |d| d > GC_INTERVAL));
+    if !due {
+        return Ok(());
+    }
+    fs::create_dir_all(&*DIR)?;
+    fs::write(&path, b"")?;
+    spawn("gc", || collect_garbage(&referenced))
+}
+
+fn collect_garbage(referenced: &HashSet<[u8; 20]>) -> Result<()> {
+    let referenced: HashSet<_> = referenced.iter().map(|c| to_hex(*c)).collect();
+    let mut kept = Vec::new();
+    let mut removed = false;
+    let stored = stored_sizes();
+    for (id, dir) in entry_dirs()? {
+        if in_flight(&dir) {
+            continue;
+        }
+        let modified = fs::metadata(dir.join("data.json"))
+            .and_then(|m| m.modified())
+            .ok();
+        // Items that were looked up on their own aren't referenced by any
+        // command, they are kept for a while after they were last updated.
+        let recent = modified
+            .and_then(|m| m.elapsed().ok())
+            .is_some_and(|age| age < GC_INTERVAL);
+        if referenced.contains(&id) || recent {
+            let size = dir_size(&dir) + stored.get(&id).copied().unwrap_or_default();
+            kept.push((modified, size, id, dir));
+        } else {
+            log::info!("removing unreferenced cache entry {id}");
+            remove(&id, &dir)?;
+            removed = true;
+        }
+    }
+
+    // Remove the least recently updated entries until the rest fit.
+    kept.sort_by_key(|(modified, ..)| std::cmp::Reverse(*modified));
+    let mut total = 0;
+    for (_, size, id, dir) in kept {
+        total += size;
+        if total > MAX_SIZE {
+            log::info!("removing cache entry {id} over the size limit");
+            remove(&id, &dir)?;
+            removed = true;
+        }
+    }
+
+    if removed {
+        compact()?;
+    }
+
+    // Entries used to be stored in a directory named after the command.
+    for entry in fs::read_dir(&*DIR)? {
+        let path = entry?.path();
+        if path.join("data.json").exists() {
+            log::info!("removing old cache entry {}", path.display());
+            fs::remove_dir_all(path)?;
+        }
+    }
+
+    logger::remove_old()?;
+    Ok(())
+}
+
+/// Returns the id and directory of every cache entry.
+fn entry_dirs() -> Result<Vec<(String, PathBuf)>> {
+    let dir = DIR.join("entries");
+    let read_dir = match fs::read_dir(&dir) {
+        Ok(read_dir) => read_dir,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut dirs = Vec::new();
+    for entry in read_dir {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            if let Ok(id) = entry.file_name().into_string() {
+                dirs.push((id, entry.path()));
+            }
+        }
+    }
+    Ok(dirs)
+}
+
+/// Returns the total size of the files in the directory.
+/// Returns the space used by each entry's items in the SQLite database, which
+/// is counted as part of the size of the entry.
+fn stored_sizes() -> HashMap<String, u64> {
+    #[cfg(feature = "sqlite")]
+    if CONFIG.backend == Backend::Sqlite {
+        match sqlite::sizes() {
+            Ok(sizes) => return sizes,
+            Err(err) => log::warn!("failed to get the size of the database: {err:#}"),
+        }
+    }
+    HashMap::new()
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok()?.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+fn to_hex(checksum: [u8; 20]) -> String {
+    checksum.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Returns the directory of the cache entry, named after the checksum so that
+/// different entries never collide.
+fn entry_dir(checksum: [u8; 20]) -> PathBuf {
+    DIR.join("entries").join(to_hex(checksum))
+}
+
+/// Whether the data is older than the interval, data with no interval is
+/// only updated when it is missing. Partial data is always stale, if no update
+/// is in flight then the one that fetched it failed or crashed and it would
+/// never be completed otherwise.
+fn is_stale(curr: &Cache, interval: Option<Duration>) -> bool {
+    curr.progress.is_some()
+        || interval.is_some_and(|interval| {
+            SystemTime::now()
+                .duration_since(curr.modified)
+                .map_or(true, |d| d > interval)
+        })
+}
+
+/// Reads the cache file, returning `None` if it doesn't exist or if it was
+/// written for a different checksum or in a format that can't be migrated.
+fn read(path: &Path, checksum: [u8; 20]) -> Result<Option<Cache>> {
+    match fs::read(path) {
+        Ok(data) => Ok(json::from_slice::<Cache>(&data)
+            .ok()
+            .filter(|c| c.checksum == checksum)
+            .and_then(migrate)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Upgrades data written in an older format. None of the older formats can
+/// be upgraded without fetching the data again, so they are discarded:
+///
+/// - Version 0 stored the raw GraphQL nodes.
+/// - Version 1 stored records without descriptions.
+/// - Version 2 stored records without the creation time, milestone,
+///   confidential and draft fields.
+fn migrate(cache: Cache) -> Option<Cache> {
+    match cache.version {
+        VERSION => Some(cache),
+        version => {
+            log::info!("discarding cache entry {} in format {version}", cache.key);
+            None
+        }
+    }
+}
+
+fn unix_epoch() -> SystemTime {
+    SystemTime::UNIX_EPOCH
+}
+
+fn read_failure<E: DeserializeOwned>(dir: &Path) -> Option<Failure<E>> {
+    let data = fs::read(dir.join("error.json")).ok()?;
+    json::from_slice(&data).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_discards_older_formats() {
+        // The format before it was versioned had no key or sync time.
+        let data = br#"{
+            "checksum": [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            "modified": {"secs_since_epoch": 0, "nanos_since_epoch": 0},
+            "data": []
+        }"#;
+        let cache: Cache = json::from_slice(data).unwrap();
+        assert_eq!(cache.version, 0);
+        assert_eq!(cache.synced, SystemTime::UNIX_EPOCH);
+        assert!(migrate(cache).is_none());
     }
 }
@@ -1,65 +1,318 @@
 use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
 
+use anyhow::{anyhow, Result};
 use once_cell::sync::Lazy;
 
+use crate::human;
+
 pub static CONFIG: Lazy<Config> = Lazy::new(Config::load);
 
+/// The name of the instance configured using `GITLAB_URL` and `GITLAB_TOKEN`.
+const DEFAULT_INSTANCE: &str = "default";
+
 #[derive(Debug)]
 pub struct Config {
-    pub token: Option<String>,
     pub user: Option<String>,
     pub shortcuts: bool,
+    /// How long cached data is used before it is refreshed in the background,
+    /// `None` only refreshes it when asked to.
+    pub ttl: Option<Duration>,
+    pub backend: Backend,
+    pub http: Http,
+    pub instances: Vec<Instance>,
     pub commands: Vec<Command>,
+    /// Descriptions of the options that have invalid values and were ignored.
+    pub invalid: Vec<String>,
+}
+
+/// Options applied to every HTTP request.
+#[derive(Debug)]
+pub struct Http {
+    pub connect_timeout: Duration,
+    pub timeout: Duration,
+    pub proxy: Option<String>,
+    pub ca_bundle: Option<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+}
+
+/// Where the cached issues and merge requests are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// A JSON file per cache entry.
+    Json,
+    /// A SQLite database with a full-text index.
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub name: String,
+    pub url: String,
+    pub token: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct Command {
     pub kind: Kind,
+    pub scope: Scope,
     pub name: String,
-    pub project: String,
+    pub path: String,
+    pub instance: Instance,
+    pub ttl: Option<Duration>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Kind {
     Issues,
     MergeRequests,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Project,
+    Group,
+}
+
 impl Config {
     fn load() -> Self {
-        let mut token = None;
         let mut user = None;
-        let mut shortcuts = false;
+        let mut shortcuts = true;
+        let mut ttl = Some(Duration::from_secs(15));
+        let mut ttls = Vec::new();
+        let mut backend = Backend::Json;
+        let mut http = Http::default();
+        let mut instances = vec![Instance::new(DEFAULT_INSTANCE)];
         let mut commands = Vec::new();
-        for (k, v) in env::vars() {
-            if v.is_empty() {
-                continue;
+        let mut invalid = Vec::new();
+
+        let vars: Vec<_> = env::vars().filter(|(_, v)| !v.is_empty()).collect();
+
+        // Instances need to be known before the commands that refer to them.
+        for (k, v) in &vars {
+            if let Some(name) = instance_name(k, "GITLAB_URL") {
+                let url = v.trim_end_matches('/').to_owned();
+                instance_mut(&mut instances, &name).url = url;
+            } else if let Some(name) = instance_name(k, "GITLAB_TOKEN") {
+                instance_mut(&mut instances, &name).token = Some(v.clone());
             }
+        }
+
+        for (k, v) in vars {
             if k == "GITLAB_USER" {
                 user = Some(v);
-            } else if k == "GITLAB_SHORTCUTS" && matches!(&*v, "1" | "true") {
-                shortcuts = true;
-            } else if k == "GITLAB_TOKEN" {
-                token = Some(v);
-            } else if let Some(name) = k.strip_prefix("GITLAB_ISSUES_") {
-                commands.push(Command {
-                    kind: Kind::Issues,
-                    name: name.to_lowercase().replace('_', "-"),
-                    project: v,
-                });
-            } else if let Some(name) = k.strip_prefix("GITLAB_MERGE_REQUESTS_") {
+            } else if k == "GITLAB_SHORTCUTS" {
+                shortcuts = !matches!(&*v, "0" | "false");
+            } else if k == "GITLAB_CACHE_TTL" {
+                match parse_ttl(&v) {
+                    Some(t) => ttl = t,
+                    None => invalid.push(invalid_duration(&k, &v)),
+                }
+            } else if let Some(name) = k.strip_prefix("GITLAB_CACHE_TTL_") {
+                match parse_ttl(&v) {
+                    Some(ttl) => ttls.push((to_name(name), ttl)),
+                    None => invalid.push(invalid_duration(&k, &v)),
+                }
+            } else if k == "GITLAB_CACHE_BACKEND" {
+                match parse_backend(&v) {
+                    Some(b) => backend = b,
+                    None => invalid.push(format!("{k} is not a supported backend: `{v}`")),
+                }
+            } else if k == "GITLAB_CONNECT_TIMEOUT" {
+                match parse_duration(&v) {
+                    Some(d) => http.connect_timeout = d,
+                    None => invalid.push(invalid_duration(&k, &v)),
+                }
+            } else if k == "GITLAB_TIMEOUT" {
+                match parse_duration(&v) {
+                    Some(d) => http.timeout = d,
+                    None => invalid.push(invalid_duration(&k, &v)),
+                }
+            } else if k == "GITLAB_PROXY" {
+                http.proxy = Some(v);
+            } else if k == "GITLAB_CA_BUNDLE" {
+                http.ca_bundle = Some(PathBuf::from(v));
+            } else if k == "GITLAB_CLIENT_CERT" {
+                http.client_cert = Some(PathBuf::from(v));
+            } else if k == "GITLAB_CLIENT_KEY" {
+                http.client_key = Some(PathBuf::from(v));
+            } else if let Some((kind, scope, name)) = command_name(&k) {
+                let (instance, path) = parse_path(&mut instances, v);
                 commands.push(Command {
-                    kind: Kind::MergeRequests,
-                    name: name.to_lowercase().replace('_', "-"),
-                    project: v,
+                    kind,
+                    scope,
+                    name,
+                    path,
+                    instance,
+                    ttl: None,
                 });
             }
         }
+
+        // Commands without their own TTL use the global one.
+        for cmd in &mut commands {
+            cmd.ttl = match ttls.iter().find(|(name, _)| *name == cmd.name) {
+                Some((_, ttl)) => *ttl,
+                None => ttl,
+            };
+        }
+
         Config {
-            token,
             user,
             shortcuts,
+            ttl,
+            backend,
+            http,
+            instances,
             commands,
+            invalid,
+        }
+    }
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(60),
+            proxy: None,
+            ca_bundle: None,
+            client_cert: None,
+            client_key: None,
+        }
+    }
+}
+
+impl Backend {
+    /// Whether item descriptions are indexed, words are only matched against
+    /// descriptions if they are.
+    pub fn has_full_text(self) -> bool {
+        match self {
+            Self::Json => false,
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite => true,
+        }
+    }
+}
+
+impl Instance {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            url: "https://gitlab.com".into(),
+            token: None,
         }
     }
+
+    /// Returns the token for this instance or an error naming the
+    /// environment variable that should be set.
+    pub fn token(&self) -> Result<&str> {
+        self.token.as_deref().ok_or_else(|| {
+            let var = if self.name == DEFAULT_INSTANCE {
+                "GITLAB_TOKEN".to_owned()
+            } else {
+                format!("GITLAB_TOKEN_{}", to_var(&self.name))
+            };
+            anyhow!("{var} environment variable is not set!")
+        })
+    }
+}
+
+/// Returns the instance name for an environment variable like `GITLAB_URL` or
+/// `GITLAB_URL_<NAME>`.
+fn instance_name(var: &str, prefix: &str) -> Option<String> {
+    match var.strip_prefix(prefix)? {
+        "" => Some(DEFAULT_INSTANCE.to_owned()),
+        suffix => suffix.strip_prefix('_').map(to_name),
+    }
+}
+
+/// Returns the instance with the given name, adding it if it does not exist.
+fn instance_mut<'a>(instances: &'a mut Vec<Instance>, name: &str) -> &'a mut Instance {
+    match instances.iter().position(|i| i.name == name) {
+        Some(i) => &mut instances[i],
+        None => {
+            instances.push(Instance::new(name));
+            instances.last_mut().unwrap()
+        }
+    }
+}
+
+/// Returns the kind, scope and name of the command defined by an environment
+/// variable like `GITLAB_ISSUES_<NAME>` or `GITLAB_GROUP_ISSUES_<NAME>`.
+fn command_name(var: &str) -> Option<(Kind, Scope, String)> {
+    const PREFIXES: &[(&str, Kind, Scope)] = &[
+        ("GITLAB_ISSUES_", Kind::Issues, Scope::Project),
+        (
+            "GITLAB_MERGE_REQUESTS_",
+            Kind::MergeRequests,
+            Scope::Project,
+        ),
+        ("GITLAB_GROUP_ISSUES_", Kind::Issues, Scope::Group),
+        (
+            "GITLAB_GROUP_MERGE_REQUESTS_",
+            Kind::MergeRequests,
+            Scope::Group,
+        ),
+    ];
+    PREFIXES.iter().find_map(|(prefix, kind, scope)| {
+        let name = var.strip_prefix(prefix)?;
+        Some((*kind, *scope, to_name(name)))
+    })
+}
+
+/// Parses a command value of the form `[instance:]path`.
+fn parse_path(instances: &mut Vec<Instance>, value: String) -> (Instance, String) {
+    match value.split_once(':') {
+        Some((instance, path)) => {
+            let instance = instance_mut(instances, &to_name(instance)).clone();
+            (instance, path.to_owned())
+        }
+        None => (instances[0].clone(), value),
+    }
+}
+
+/// Parses a duration like `30`, `30s`, `10m`, or `2h`. A number without a
+/// unit is in seconds.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    match s.parse() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => human::parse_duration(s),
+    }
+}
+
+/// Parses a cache backend, `sqlite` is only supported if the workflow was
+/// built with the `sqlite` feature.
+fn parse_backend(s: &str) -> Option<Backend> {
+    match s.trim() {
+        "json" => Some(Backend::Json),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => Some(Backend::Sqlite),
+        _ => None,
+    }
+}
+
+fn invalid_duration(var: &str, value: &str) -> String {
+    format!("{var} is not a valid duration: `{value}`")
+}
+
+/// Parses a cache TTL, either a duration or `manual` to only refresh the
+/// cache when asked to.
+fn parse_ttl(s: &str) -> Option<Option<Duration>> {
+    match s.trim() {
+        "manual" => Some(None),
+        s => parse_duration(s).map(Some),
+    }
+}
+
+fn to_name(var: &str) -> String {
+    var.to_lowercase().replace('_', "-")
+}
+
+fn to_var(name: &str) -> String {
+    name.to_uppercase().replace('-', "_")
 }
@@ -0,0 +1,242 @@
+//! Fuzzy matching used to rank search results.
+//!
+//! A search word matches a title if all of its characters appear in the title
+//! in order, so that typos like "pipelne" still find "pipeline". Matches are
+//! scored higher when the characters are consecutive and start at word
+//! boundaries, and lower the more characters are skipped between them.
+
+use std::time::Duration;
+
+/// The score for each matched character.
+const MATCH: i32 = 16;
+
+/// The bonus for a character that starts a word.
+const BOUNDARY: i32 = 8;
+
+/// The bonus for a character that directly follows the previous match.
+const CONSECUTIVE: i32 = 8;
+
+/// The penalty for each character skipped between two matches.
+const GAP: i32 = 2;
+
+/// The score for a word that is only found in the description.
+const DESCRIPTION: u32 = 8;
+
+/// The bonus for an item that was updated just now, it halves every week.
+const RECENCY: u32 = 16;
+
+/// Scores how well the word matches the text, ignoring case. Returns `None`
+/// if the characters of the word don't appear in the text in order, or if they
+/// are so spread out that the match is unlikely to be what was meant.
+pub fn score(word: &str, text: &str) -> Option<u32> {
+    let word: Vec<char> = word.chars().flat_map(char::to_lowercase).collect();
+    let text: Vec<char> = text.chars().collect();
+    if word.is_empty() {
+        return Some(0);
+    }
+    let bonus: Vec<i32> = (0..text.len())
+        .map(|j| if is_boundary(&text, j) { BOUNDARY } else { 0 })
+        .collect();
+    let text: Vec<char> = text.iter().flat_map(|c| c.to_lowercase()).collect();
+    if text.len() != bonus.len() {
+        // Lowercasing changed the length, fall back to a plain substring
+        // match rather than misaligning the bonuses.
+        let text: String = text.into_iter().collect();
+        let word: String = word.into_iter().collect();
+        return text
+            .contains(&word)
+            .then(|| word.chars().count() as u32 * MATCH as u32);
+    }
+
+    // The best score of the word so far where the last matched character is
+    // at the given position in the text.
+    let mut prev: Vec<Option<i32>> = vec![None; text.len()];
+    for (i, &w) in word.iter().enumerate() {
+        let mut curr = vec![None; text.len()];
+        // The best score of the previous row, adjusted so that subtracting the
+        // gap penalty for position `j` gives the score after skipping.
+        let mut best: Option<i32> = None;
+        for j in 0..text.len() {
+            if let Some(p) = j.checked_sub(2).and_then(|k| prev[k]) {
+                let p = p + GAP * (j as i32 - 1);
+                best = Some(best.map_or(p, |b| b.max(p)));
+            }
+            if text[j] != w {
+                continue;
+            }
+            let base = MATCH + bonus[j];
+            curr[j] = if i == 0 {
+                Some(base)
+            } else {
+                let consecutive = j
+                    .checked_sub(1)
+                    .and_then(|k| prev[k])
+                    .map(|p| p + base + CONSECUTIVE);
+                let skipped = best.map(|b| b - GAP * j as i32 + base);
+                consecutive.max(skipped)
+            };
+        }
+        prev = curr;
+    }
+
+    let best = prev.into_iter().flatten().max()?;
+    let min = word.len() as i32 * MATCH;
+    (best >= min).then_some(best as u32)
+}
+
+/// Scores a search word against the title and description of an item. Titles
+/// are matched fuzzily, the description only matches if it contains the word.
+pub fn score_item(word: &str, title: &str, description: &str) -> Option<u32> {
+    let in_title = score(word, title);
+    let in_description = description
+        .to_lowercase()
+        .contains(&word.to_lowercase())
+        .then_some(DESCRIPTION);
+    in_title.max(in_description)
+}
+
+/// The bonus for an item that involves the user.
+const OURS: u32 = 8;
+
+/// Combines the score of the search words with how recently the item was
+/// updated and whether it involves the user. Items that weren't searched for
+/// by any words all rank the same.
+pub fn rank(score: u32, age: Duration, ours: bool) -> u32 {
+    if score == 0 {
+        return 0;
+    }
+    score + recency(age) + if ours { OURS } else { 0 }
+}
+
+/// Returns a bonus for recently updated items.
+fn recency(age: Duration) -> u32 {
+    let weeks = age.as_secs() / (7 * 24 * 60 * 60);
+    RECENCY.checked_shr(weeks as u32).unwrap_or(0)
+}
+
+/// Whether the character at the position starts a word.
+fn is_boundary(text: &[char], j: usize) -> bool {
+    let c = text[j];
+    match j.checked_sub(1).map(|k| text[k]) {
+        None => true,
+        Some(p) => {
+            (!p.is_alphanumeric() && c.is_alphanumeric()) || (p.is_lowercase() && c.is_uppercase())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn score_exact() {
+        assert!(score("pipeline", "Fix the pipeline").is_some());
+        assert!(score("PIPELINE", "fix the pipeline").is_some());
+        assert_eq!(score("", "anything"), Some(0));
+    }
+
+    #[test]
+    fn score_typo() {
+        assert!(score("pipelne", "Fix the pipeline").is_some());
+        assert!(score("pplne", "Fix the pipeline").is_some());
+    }
+
+    #[test]
+    fn score_no_match() {
+        assert_eq!(score("pipeline", "Fix the build"), None);
+        assert_eq!(score("xyz", "Fix the build"), None);
+        assert_eq!(score("abc", ""), None);
+    }
+
+    #[test]
+    fn score_too_spread_out() {
+        assert_eq!(score("fix", "feature: index updates"), None);
+    }
+
+    #[test]
+    fn score_prefers_exact() {
+        let exact = score("pipeline", "Fix the pipeline").unwrap();
+        let typo = score("pipelne", "Fix the pipeline").unwrap();
+        assert!(exact > typo);
+    }
+
+    #[test]
+    fn score_prefers_word_boundary() {
+        let boundary = score("cache", "Clear the cache").unwrap();
+        let inner = score("cache", "Use precaches").unwrap();
+        assert!(boundary > inner);
+    }
+
+    #[test]
+    fn score_prefers_camel_case_boundary() {
+        let boundary = score("mr", "Load MergeRequest").unwrap();
+        let inner = score("mr", "Hammers").unwrap_or(0);
+        assert!(boundary > inner);
+    }
+
+    #[test]
+    fn score_prefers_consecutive() {
+        let consecutive = score("deploy", "Deploy to staging").unwrap();
+        let scattered = score("deploy", "Do extra prod loyalty").unwrap_or(0);
+        assert!(consecutive > scattered);
+    }
+
+    #[test]
+    fn score_picks_best_alignment() {
+        // The first "p" is not where the best match starts.
+        let s = score("pipe", "Update the pipe").unwrap();
+        assert_eq!(s, score("pipe", "pipe").unwrap());
+    }
+
+    #[test]
+    fn score_non_ascii() {
+        assert!(score("über", "Fix Über button").is_some());
+        assert!(score("İ", "İstanbul").is_some());
+    }
+
+    #[test]
+    fn score_item_title_or_description() {
+        assert!(score_item("pipeline", "Fix the pipeline", "").is_some());
+        assert!(score_item("runner", "Fix the pipeline", "The runner is down").is_some());
+        assert_eq!(score_item("docker", "Fix the pipeline", "The runner"), None);
+    }
+
+    #[test]
+    fn score_item_prefers_title() {
+        let title = score_item("runner", "Fix the runner", "").unwrap();
+        let description = score_item("runner", "Fix the build", "The runner").unwrap();
+        assert!(title > description);
+    }
+
+    #[test]
+    fn recency_decays() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        assert_eq!(recency(Duration::ZERO), RECENCY);
+        assert_eq!(recency(day), RECENCY);
+        assert_eq!(recency(day * 7), RECENCY / 2);
+        assert_eq!(recency(day * 14), RECENCY / 4);
+        assert_eq!(recency(day * 365), 0);
+    }
+
+    #[test]
+    fn rank_without_words() {
+        assert_eq!(rank(0, Duration::ZERO, true), 0);
+    }
+
+    #[test]
+    fn rank_prefers_recent_and_ours() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let score = score("pipeline", "Fix the pipeline").unwrap();
+        assert!(rank(score, day, false) > rank(score, day * 30, false));
+        assert!(rank(score, day, true) > rank(score, day, false));
+    }
+
+    #[test]
+    fn rank_prefers_better_matches() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let exact = score("pipeline", "Fix the pipeline").unwrap();
+        let typo = score("pipelne", "Fix the pipeline").unwrap();
+        assert!(rank(exact, day * 30, false) > rank(typo, Duration::ZERO, true));
+    }
+}
@@ -1,31 +1,103 @@
-use std::io::prelude::*;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fmt;
+use std::iter;
+use std::time::{Duration, SystemTime};
 
-use anyhow::{anyhow, Context, Result};
-use chrono::DateTime;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json as json;
 
-use crate::config::CONFIG;
-use crate::{Issue, MergeRequest};
+use crate::cache::{self, Progress};
+use crate::config::{Backend, Instance, Kind, Scope, CONFIG};
+use crate::http;
+use crate::query;
+#[cfg(feature = "sqlite")]
+use crate::sqlite;
+use crate::{Issue, MergeRequest, Role, State};
 
 type ParseFn<T> = fn(json::Value) -> Result<T>;
 
+/// An error returned while fetching data from GitLab.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum Error {
+    /// The project or group doesn't exist or the token can't access it.
+    NotFound { path: String },
+    /// The token is invalid, expired, or revoked.
+    Unauthorized { url: String },
+    /// The token is missing the `read_api` scope.
+    InsufficientScope { url: String },
+    /// Too many requests were made to the instance.
+    RateLimited,
+    /// The instance failed to handle the request.
+    Server { status: u32 },
+    /// The instance couldn't be reached or the connection failed.
+    Network { message: String },
+    /// Any other error.
+    Other { message: String },
+}
+
 struct Query<'a, T> {
-    name: &'a str,
-    project: &'a str,
-    query: &'a str,
-    page_info_ptr: &'a str,
-    nodes_ptr: &'a str,
+    instance: &'a Instance,
+    name: String,
+    variables: Variables,
+    /// Whether `$user` is the username of the current user, which is looked
+    /// up before the query is fetched.
+    current_user: bool,
+    query: String,
+    /// The object in the response that is null if the token is not valid.
+    root_ptr: String,
+    page_info_ptr: String,
+    nodes_ptr: String,
+    ttl: Option<Duration>,
     parse_fn: ParseFn<T>,
 }
 
-#[derive(Debug, Serialize)]
-struct Variables<'a> {
-    project: &'a str,
+/// The issues or merge requests of a project or group in a single state, each
+/// of which is stored in its own cache entry.
+#[derive(Debug, Clone, Copy)]
+struct Source<'a> {
+    instance: &'a Instance,
+    kind: Kind,
+    scope: Scope,
+    path: &'a str,
+    state: Option<State>,
+    ttl: Option<Duration>,
+}
+
+/// A source that is being fetched as part of a batch.
+struct Job<'a> {
+    source: Source<'a>,
+    update: cache::Update,
+    /// The previous data if only the items updated since then are fetched.
+    prev: Option<Data>,
+    after: Option<String>,
+    items: Vec<json::Value>,
+    pages: usize,
+    /// The total number of pages, known after the first page is fetched.
+    total: Option<usize>,
+    result: Option<Result<(), Error>>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct Variables {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
     after: Option<String>,
 }
 
+/// The data that is stored in the cache. The items are normalized `Issue` or
+/// `MergeRequest` records so that they don't need to be parsed from the
+/// GraphQL response on every keystroke.
+#[derive(Debug, Deserialize, Serialize)]
+struct Data<T = json::Value> {
+    /// The most recent `updated_at` of all the items that have been fetched.
+    updated_at: Option<DateTime<Utc>>,
+    items: Vec<T>,
+}
+
 #[derive(Deserialize)]
 struct PageInfo {
     #[serde(rename = "endCursor")]
@@ -38,110 +110,798 @@ impl<T> Query<'_, T> {
     fn checksum(&self) -> [u8; 20] {
         use sha1::*;
         let mut hasher = Sha1::new();
+        hash_instance(&mut hasher, self.instance);
+        hash_backend(&mut hasher);
         hasher.update(self.name.as_bytes());
-        hasher.update(self.project.as_bytes());
+        hasher.update(json::to_vec(&self.variables).unwrap());
         hasher.update(self.query.as_bytes());
-        hasher.finalize().try_into().unwrap()
+        hasher.finalize().into()
     }
 }
 
-fn fetch_and_parse<T>(q: Query<'_, T>) -> Result<Vec<T>> {
-    let token = CONFIG
-        .token
-        .as_ref()
-        .ok_or_else(|| anyhow!("GITLAB_TOKEN environment variable is not set!"))?;
+/// Issues or merge requests loaded from the cache.
+pub type Loaded<T> = cache::Loaded<Vec<T>, Error>;
 
-    let mut r = crate::cache::load(q.name, q.checksum(), || fetch_all(&q, token))?;
-    let resps = r
-        .as_array_mut()
-        .context("cache value is not an array")?
-        .drain(..);
+/// Loads the items of the query without waiting for them to be fetched,
+/// returns `None` if they haven't been fetched yet.
+fn fetch_and_parse<T>(q: Query<'_, T>, search: &query::Query) -> Result<Option<Loaded<T>>>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let token = q.instance.token()?;
+    let checksum = q.checksum();
 
-    let mut nodes = Vec::new();
-    for resp in resps {
-        let ns: Vec<json::Value> = lookup(&resp, q.nodes_ptr)?;
-        nodes.extend(ns);
+    let interval = q.ttl.map(|ttl| ttl * http::throttle(&q.instance.url));
+    let data = cache::try_load::<_, Error>(&q.name, checksum, interval, || {
+        let update = match cache::begin(&q.name, checksum)? {
+            Some(update) => update,
+            None => return Ok(()),
+        };
+        let mut client = http::Client::new()?;
+        match fetch_nodes(&q, &mut client, token, &update) {
+            Ok(items) => {
+                let updated_at = newest(&items);
+                let data = prepare(&update, Data { updated_at, items })?;
+                update.commit(data)
+            }
+            Err(err) => {
+                let err = Error::from(err);
+                update.fail(&err)?;
+                Err(err.into())
+            }
+        }
+    })?;
+    data.map(|data| data.try_map(|data| parse_items(data, checksum, search)))
+        .transpose()
+}
+
+/// Deserializes the cached items, which are missing if the first page hasn't
+/// been fetched yet. If the items are stored in the SQLite database then only
+/// the ones that match the query are loaded.
+#[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+fn parse_items<T>(data: json::Value, checksum: [u8; 20], search: &query::Query) -> Result<Vec<T>>
+where
+    T: DeserializeOwned,
+{
+    if data.is_null() {
+        return Ok(Vec::new());
     }
-    nodes.into_iter().map(q.parse_fn).collect()
+    match CONFIG.backend {
+        Backend::Json => {
+            let data: Data<T> = json::from_value(data)?;
+            Ok(data.items)
+        }
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => sqlite::search(&cache::id(checksum), search),
+    }
+}
+
+/// Returns the data to write to the cache. If the SQLite backend is enabled
+/// the items are stored in the database instead.
+#[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+fn prepare(update: &cache::Update, data: Data) -> Result<json::Value> {
+    let data = match CONFIG.backend {
+        Backend::Json => data,
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => {
+            sqlite::replace(&update.id(), &data.items)?;
+            Data {
+                updated_at: data.updated_at,
+                items: Vec::new(),
+            }
+        }
+    };
+    Ok(json::to_value(data)?)
 }
 
-fn fetch_all<T>(q: &Query<'_, T>, token: &str) -> Result<json::Value> {
-    let mut array = Vec::new();
-    let mut variables = Variables {
-        project: q.project,
-        after: None,
+/// Returns the previous data of the entry if it can be refreshed
+/// incrementally.
+fn previous(update: &mut cache::Update) -> Result<Option<Data>> {
+    let prev = match update
+        .take_previous()
+        .and_then(|v| json::from_value::<Data>(v).ok())
+        .filter(|prev| prev.updated_at.is_some())
+    {
+        Some(prev) => prev,
+        None => return Ok(None),
     };
+    #[cfg(feature = "sqlite")]
+    if CONFIG.backend == Backend::Sqlite {
+        let items = sqlite::items(&update.id())?;
+        return Ok(Some(Data { items, ..prev }));
+    }
+    Ok(Some(prev))
+}
+
+/// Parses a node from a GraphQL response into a record to store in the cache.
+fn normalize<T: Serialize>(parse_fn: ParseFn<T>, node: json::Value) -> Result<json::Value> {
+    Ok(json::to_value(parse_fn(node)?)?)
+}
+
+/// Fetches the nodes from every page of the query, returning them as
+/// normalized records.
+fn fetch_nodes<T: Serialize>(
+    q: &Query<'_, T>,
+    client: &mut http::Client,
+    token: &str,
+    update: &cache::Update,
+) -> Result<Vec<json::Value>> {
+    let mut variables = q.variables.clone();
+    if q.current_user {
+        variables.user = Some(current_user(client, &q.instance.url, token)?);
+    }
+    let mut nodes = Vec::new();
+    let mut pages = 0;
 
     loop {
-        let resp = fetch(q.query, &variables, token)?;
-        let page_info: PageInfo = lookup(&resp, q.page_info_ptr)?;
-        array.push(resp);
+        update.check_deadline()?;
+        let resp = fetch(client, &q.instance.url, &q.query, &variables, token)?;
+        if matches!(resp.pointer(&q.root_ptr), None | Some(json::Value::Null)) {
+            // The current user is missing if the token is not valid.
+            return Err(Error::Unauthorized {
+                url: q.instance.url.clone(),
+            }
+            .into());
+        }
+        let page_info: PageInfo = lookup(&resp, &q.page_info_ptr)?;
+        let ns: Vec<json::Value> = lookup(&resp, &q.nodes_ptr)?;
+        for n in ns {
+            nodes.push(normalize(q.parse_fn, n)?);
+        }
         if !page_info.has_next {
-            break Ok(json::Value::Array(array));
+            break Ok(nodes);
         }
+        pages += 1;
+        write_partial(update, &nodes, Progress { pages, total: None });
         variables.after = Some(page_info.cursor);
     }
 }
 
-fn fetch(query: &str, variables: &Variables, token: &str) -> Result<json::Value> {
-    #[derive(Debug, Serialize)]
-    struct Query<'a> {
-        query: &'a str,
-        variables: &'a Variables<'a>,
+/// Returns the username of the user that the token belongs to.
+fn current_user(client: &mut http::Client, url: &str, token: &str) -> Result<String> {
+    let resp = fetch(client, url, CURRENT_USER_QUERY, &json::Map::new(), token)?;
+    match resp.pointer("/data/currentUser/username") {
+        Some(json::Value::String(username)) => Ok(username.clone()),
+        // The current user is null if the token is not valid.
+        _ => Err(Error::Unauthorized { url: url.into() }.into()),
+    }
+}
+
+impl<'a> Source<'a> {
+    fn key(&self) -> String {
+        cache_key(self.instance, self.kind, self.scope, self.path, self.state)
+    }
+
+    fn checksum(&self) -> [u8; 20] {
+        use sha1::*;
+        let mut hasher = Sha1::new();
+        hash_instance(&mut hasher, self.instance);
+        hash_backend(&mut hasher);
+        hasher.update(self.key().as_bytes());
+        hasher.update(self.path.as_bytes());
+        hasher.update(self.connection(self.state, "a", 0).as_bytes());
+        hasher.update(self.fragment().as_bytes());
+        hasher.finalize().into()
+    }
+
+    fn field(&self) -> &'static str {
+        match self.kind {
+            Kind::Issues => "issues",
+            Kind::MergeRequests => "mergeRequests",
+        }
+    }
+
+    /// Parses a node from a GraphQL response into a record to store.
+    fn normalize(&self, node: json::Value) -> Result<json::Value> {
+        match self.kind {
+            Kind::Issues => normalize(parse_issue, node),
+            Kind::MergeRequests => normalize(parse_merge_request, node),
+        }
+    }
+
+    fn fragment(&self) -> String {
+        fragment(self.kind)
+    }
+
+    /// Renders the selection of this source's connection in a batched query
+    /// under the given alias and index for its variables.
+    fn connection(&self, state: Option<State>, alias: &str, i: usize) -> String {
+        let fragment = match self.kind {
+            Kind::Issues => "IssueFields",
+            Kind::MergeRequests => "MergeRequestFields",
+        };
+        render(CONNECTION, self.scope, state)
+            .replace("{alias}", alias)
+            .replace("{i}", &i.to_string())
+            .replace("{field}", self.field())
+            .replace("{fragment}", fragment)
+    }
+
+    /// Returns how often to update the cache, slowing down as the rate limit
+    /// gets close.
+    fn interval(&self) -> Option<Duration> {
+        self.ttl.map(|ttl| ttl * http::throttle(&self.instance.url))
+    }
+
+    /// Returns the other sources on the same instance whose cache is due to
+    /// be updated. Only open items are updated in the background, other
+    /// states are only fetched when asked for.
+    fn due(&self) -> impl Iterator<Item = Source<'a>> + 'a {
+        let instance = self.instance.name.as_str();
+        CONFIG
+            .commands
+            .iter()
+            .filter(move |cmd| cmd.instance.name == instance)
+            .map(|cmd| Source {
+                instance: &cmd.instance,
+                kind: cmd.kind,
+                scope: cmd.scope,
+                path: &cmd.path,
+                state: Some(State::Opened),
+                ttl: cmd.ttl,
+            })
+            .filter(|s| cache::is_due(s.checksum(), s.interval()))
+    }
+}
+
+/// Hashes the instance and a fingerprint of its token, so that data fetched
+/// for one account or instance is never shown for another.
+fn hash_instance(hasher: &mut sha1::Sha1, instance: &Instance) {
+    use sha1::*;
+    hasher.update(instance.name.as_bytes());
+    hasher.update(instance.url.as_bytes());
+    if let Some(token) = &instance.token {
+        hasher.update(Sha1::digest(token.as_bytes()));
+    }
+}
+
+/// Hashes the backend the items are stored in, so that switching backends
+/// never reads an entry whose items are stored in the other one.
+/// Renders the fields of the kind of item. Descriptions are only requested if
+/// the backend indexes them, since they are never matched otherwise and can
+/// be large.
+fn fragment(kind: Kind) -> String {
+    let fields = match kind {
+        Kind::Issues => ISSUE_FRAGMENT,
+        Kind::MergeRequests => MERGE_REQUEST_FRAGMENT,
+    };
+    let description = match CONFIG.backend.has_full_text() {
+        true => "description",
+        false => "",
+    };
+    fields.replace("{description}", description)
+}
+
+fn hash_backend(hasher: &mut sha1::Sha1) {
+    use sha1::*;
+    let name = match CONFIG.backend {
+        Backend::Json => "json",
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => "sqlite",
+    };
+    hasher.update(name.as_bytes());
+}
+
+/// Loads the items of the source. Items in all states are only fetched up to
+/// [`MAX_PAGES`], so the open items are merged into them so that none of the
+/// items listed by default are missing.
+fn load<T: DeserializeOwned>(source: Source<'_>, search: &query::Query) -> Result<Loaded<T>> {
+    if source.state.is_some() {
+        return load_source(source, search);
     }
+    let open = Source {
+        state: Some(State::Opened),
+        ..source
+    };
+    let all = load_source(source, search)?;
+    merge_open(all, Some(load_source(open, search)?))
+}
 
-    let mut buf = Vec::new();
-    let mut easy = curl::easy::Easy::new();
-    let mut data = &*serde_json::to_vec(&Query { query, variables })?;
+fn load_source<T: DeserializeOwned>(
+    source: Source<'_>,
+    search: &query::Query,
+) -> Result<Loaded<T>> {
+    let token = source.instance.token()?;
+    let checksum = source.checksum();
 
-    easy.fail_on_error(true)?;
-    easy.follow_location(true)?;
-    easy.http_headers({
-        let mut hl = curl::easy::List::new();
-        hl.append(&format!("Authorization: Bearer {token}"))?;
-        hl.append("Content-Type: application/json")?;
-        hl
+    let data = cache::load::<_, Error>(&source.key(), checksum, source.interval(), || {
+        refresh(source, token)
     })?;
-    easy.post(true)?;
-    easy.url("https://gitlab.com/api/graphql")?;
+    data.try_map(|data| parse_items(data, checksum, search))
+}
 
-    {
-        let mut t = easy.transfer();
-        t.read_function(|into| Ok(data.read(into).unwrap()))?;
-        t.write_function(|data| {
-            buf.extend_from_slice(data);
-            Ok(data.len())
-        })?;
-        t.perform()?;
+/// Like [`load`] but only returns what is cached, `None` if nothing is.
+fn cached<T: DeserializeOwned>(
+    source: Source<'_>,
+    search: &query::Query,
+) -> Result<Option<Loaded<T>>> {
+    if source.state.is_some() {
+        return cached_source(source, search);
+    }
+    let open = Source {
+        state: Some(State::Opened),
+        ..source
+    };
+    match cached_source(source, search)? {
+        Some(all) => merge_open(all, cached_source(open, search)?).map(Some),
+        None => Ok(None),
     }
+}
 
-    Ok(serde_json::from_slice(&buf)?)
+fn cached_source<T: DeserializeOwned>(
+    source: Source<'_>,
+    search: &query::Query,
+) -> Result<Option<Loaded<T>>> {
+    let checksum = source.checksum();
+    cache::cached(checksum)?
+        .map(|data| data.try_map(|data| parse_items(data, checksum, search)))
+        .transpose()
 }
 
-pub fn issues(name: &str, project: &str) -> Result<Vec<Issue>> {
-    let query = r#"
-query($project: ID!, $after: String) {
-    project(fullPath: $project) {
-        issues(state: opened, after: $after) {
-            nodes {
-                title
-                author {
-                    name
-                    username
-                }
-                assignees {
-                    nodes {
-                        name
-                        username
-                    }
+/// Merges the open items into the items in all states, skipping the ones that
+/// are already there.
+fn merge_open<T: DeserializeOwned>(
+    mut all: Loaded<json::Value>,
+    open: Option<Loaded<json::Value>>,
+) -> Result<Loaded<T>> {
+    if let Some(open) = open {
+        let ids: HashSet<_> = all.data.iter().filter_map(id).map(str::to_owned).collect();
+        all.data.extend(
+            open.data
+                .into_iter()
+                .filter(|n| !id(n).is_some_and(|id| ids.contains(id))),
+        );
+        all.modified = all.modified.min(open.modified);
+        all.error = all.error.or(open.error);
+        all.progress = all.progress.or(open.progress);
+    }
+    all.try_map(|items| {
+        items
+            .into_iter()
+            .map(|n| Ok(json::from_value(n)?))
+            .collect()
+    })
+}
+
+/// Updates the cache for the given source along with every other source on
+/// the same instance that is due, batching them into as few requests as
+/// possible.
+fn refresh(primary: Source<'_>, token: &str) -> Result<()> {
+    let mut keys = HashSet::new();
+    let mut jobs = Vec::new();
+    for source in iter::once(primary).chain(primary.due()) {
+        if !keys.insert(source.key()) {
+            continue;
+        }
+        // Another process is already updating this source.
+        if let Some(mut update) = cache::begin(&source.key(), source.checksum())? {
+            let prev = previous(&mut update)?;
+            jobs.push(Job {
+                source,
+                update,
+                prev,
+                after: None,
+                items: Vec::new(),
+                pages: 0,
+                total: None,
+                result: None,
+            });
+        }
+    }
+    log::info!(
+        "updating {} cache entries on {}",
+        jobs.len(),
+        primary.instance.url
+    );
+
+    let mut client = http::Client::new()?;
+    loop {
+        for job in jobs.iter_mut().filter(|j| j.result.is_none()) {
+            if let Err(err) = job.update.check_deadline() {
+                job.result = Some(Err(Error::from(err)));
+            }
+        }
+        let mut pending: Vec<_> = jobs.iter_mut().filter(|j| j.result.is_none()).collect();
+        if pending.is_empty() {
+            break;
+        }
+        for batch in pending.chunks_mut(MAX_BATCH) {
+            if let Err(err) = fetch_batch(&mut client, primary.instance, token, batch) {
+                let err = Error::from(err);
+                for job in batch.iter_mut().filter(|j| j.result.is_none()) {
+                    job.result = Some(Err(err.clone()));
                 }
-                createdAt
-                webUrl
-                labels {
-                    nodes {
-                        title
-                    }
+            }
+        }
+    }
+
+    let mut primary_result = Ok(());
+    for job in jobs {
+        let key = job.source.key();
+        let result = match job.result {
+            Some(Err(err)) => {
+                job.update.fail(&err)?;
+                Err(err.into())
+            }
+            _ => {
+                let data = finish(job.source, job.prev, job.items);
+                let data = prepare(&job.update, data)?;
+                job.update.commit(data)
+            }
+        };
+        match result {
+            Err(err) if key == primary.key() => primary_result = Err(err),
+            Err(err) => log::error!("failed to update {key}: {err:#}"),
+            Ok(()) => {}
+        }
+    }
+    primary_result
+}
+
+/// Fetches the next page of each job in a single request.
+fn fetch_batch(
+    client: &mut http::Client,
+    instance: &Instance,
+    token: &str,
+    batch: &mut [&mut Job<'_>],
+) -> Result<()> {
+    let mut decls = Vec::new();
+    let mut selections = String::new();
+    let mut fragments = Vec::new();
+    let mut variables = json::Map::new();
+    for (i, job) in batch.iter().enumerate() {
+        let source = &job.source;
+        let state = job.state();
+        decls.push(format!(
+            "$path{i}: ID!, $updatedAfter{i}: Time, $after{i}: String"
+        ));
+        selections.push_str(&source.connection(state, &format!("a{i}"), i));
+        if !fragments.contains(&source.fragment()) {
+            fragments.push(source.fragment());
+        }
+        variables.insert(format!("path{i}"), source.path.into());
+        variables.insert(
+            format!("updatedAfter{i}"),
+            json::to_value(job.prev.as_ref().and_then(|p| p.updated_at))?,
+        );
+        variables.insert(format!("after{i}"), json::to_value(&job.after)?);
+    }
+    let query = format!("query({}) {{\n{selections}}}\n", decls.join(", ")) + &fragments.concat();
+
+    let resp = post(client, &instance.url, &query, &variables, token)?;
+
+    // Errors under an alias only fail the job it belongs to, any other error
+    // fails the whole batch.
+    let mut failed = vec![None; batch.len()];
+    for (path, message) in errors(&resp) {
+        let alias = path.and_then(|p| p.strip_prefix('a')?.parse::<usize>().ok());
+        match alias.filter(|&i| i < batch.len()) {
+            Some(i) => failed[i] = failed[i].take().or(Some(message)),
+            None => return Err(Error::from_message(&instance.url, message).into()),
+        }
+    }
+
+    for (i, job) in batch.iter_mut().enumerate() {
+        if let Some(message) = failed[i] {
+            job.result = Some(Err(Error::from_message(&instance.url, message)));
+            continue;
+        }
+        let ptr = format!("/data/a{i}");
+        // A missing project or group is not an error in GraphQL.
+        if matches!(resp.pointer(&ptr), None | Some(json::Value::Null)) {
+            job.result = Some(Err(Error::NotFound {
+                path: job.source.path.into(),
+            }));
+            continue;
+        }
+        let ptr = format!("{ptr}/{}", job.source.field());
+        let page_info: PageInfo = lookup(&resp, &format!("{ptr}/pageInfo"))?;
+        let ns: Vec<json::Value> = lookup(&resp, &format!("{ptr}/nodes"))?;
+        for n in ns {
+            job.items.push(job.source.normalize(n)?);
+        }
+        job.pages += 1;
+        let max_pages = match job.prev {
+            Some(_) => None,
+            None => max_pages(job.source.state),
+        };
+        if job.total.is_none() {
+            let count: usize = lookup(&resp, &format!("{ptr}/count"))?;
+            let total = count.div_ceil(PAGE_SIZE).max(1);
+            job.total = Some(max_pages.map_or(total, |max| total.min(max)));
+        }
+        if !page_info.has_next || max_pages == Some(job.pages) {
+            job.result = Some(Ok(()));
+        } else if job.prev.is_none() {
+            let progress = Progress {
+                pages: job.pages,
+                total: job.total,
+            };
+            write_partial(&job.update, &job.items, progress);
+        }
+        job.after = Some(page_info.cursor);
+    }
+    Ok(())
+}
+
+/// Writes the items fetched so far to the cache, a failure only means they
+/// aren't shown until the fetch is complete. Each write rewrites every item
+/// fetched so far, so they are only written when the number of pages doubles.
+fn write_partial(update: &cache::Update, items: &[json::Value], progress: Progress) {
+    if update.has_data() || !progress.pages.is_power_of_two() {
+        return;
+    }
+    let data = Data {
+        updated_at: newest(items),
+        items: items.to_vec(),
+    };
+    let result = prepare(update, data).and_then(|data| update.write_partial(data, progress));
+    if let Err(err) = result {
+        log::warn!("failed to write partial results: {err:#}");
+    }
+}
+
+impl Job<'_> {
+    /// The state to fetch, updated items are fetched in all states so that
+    /// items that changed state can be removed.
+    fn state(&self) -> Option<State> {
+        match self.prev {
+            Some(_) => None,
+            None => self.source.state,
+        }
+    }
+}
+
+/// Returns the data to store for the fetched items, merging them into the
+/// previous data if only the updated items were fetched.
+fn finish(source: Source<'_>, prev: Option<Data>, updated: Vec<json::Value>) -> Data {
+    let prev = match prev {
+        Some(prev) => prev,
+        None => {
+            let updated_at = newest(&updated);
+            return Data {
+                updated_at,
+                items: updated,
+            };
+        }
+    };
+    log::info!(
+        "fetched {} updated items for {}",
+        updated.len(),
+        source.key()
+    );
+
+    let updated_at = newest(&updated).max(prev.updated_at);
+    let ids: HashSet<_> = updated.iter().filter_map(id).map(str::to_owned).collect();
+    let mut items: Vec<_> = prev
+        .items
+        .into_iter()
+        .filter(|n| !id(n).is_some_and(|id| ids.contains(id)))
+        .collect();
+    items.extend(updated.into_iter().filter(|n| match source.state {
+        Some(state) => n.get("state").and_then(json::Value::as_str) == Some(state_arg(state)),
+        None => true,
+    }));
+    items.sort_by_key(|n| Reverse(timestamp(n, order_by(source.state))));
+
+    Data { updated_at, items }
+}
+
+/// Returns the most recent `updated_at` of the given items.
+fn newest(items: &[json::Value]) -> Option<DateTime<Utc>> {
+    items
+        .iter()
+        .filter_map(|n| timestamp(n, "/updated_at"))
+        .max()
+}
+
+fn id(node: &json::Value) -> Option<&str> {
+    node.get("id")?.as_str()
+}
+
+fn timestamp(node: &json::Value, ptr: &str) -> Option<DateTime<Utc>> {
+    node.pointer(ptr)?.as_str()?.parse().ok()
+}
+
+fn fetch(
+    client: &mut http::Client,
+    url: &str,
+    query: &str,
+    variables: &impl Serialize,
+    token: &str,
+) -> Result<json::Value> {
+    let resp = post(client, url, query, variables, token)?;
+
+    // GraphQL errors are returned with a successful status code.
+    if let Some((_, message)) = errors(&resp).next() {
+        return Err(Error::from_message(url, message).into());
+    }
+    Ok(resp)
+}
+
+/// Posts a GraphQL query to the instance, returning the response even if it
+/// contains errors.
+fn post(
+    client: &mut http::Client,
+    url: &str,
+    query: &str,
+    variables: &impl Serialize,
+    token: &str,
+) -> Result<json::Value> {
+    #[derive(Debug, Serialize)]
+    struct Query<'a, V> {
+        query: &'a str,
+        variables: &'a V,
+    }
+
+    let body = json::to_vec(&Query { query, variables })?;
+    let data = match client.post(&format!("{url}/api/graphql"), token, &body) {
+        Ok(data) => data,
+        Err(err) => match err.downcast::<http::Error>() {
+            Ok(err) => return Err(Error::from_status(url, &err).into()),
+            Err(err) => match err.downcast::<curl::Error>() {
+                Ok(err) => {
+                    let message = err.to_string();
+                    return Err(Error::Network { message }.into());
                 }
+                Err(err) => return Err(err),
+            },
+        },
+    };
+    Ok(json::from_slice(&data)?)
+}
+
+/// Returns the messages of the GraphQL errors in a response, along with the
+/// first field of the path that each error occurred at, if any.
+fn errors(resp: &json::Value) -> impl Iterator<Item = (Option<&str>, &str)> {
+    resp.get("errors")
+        .and_then(json::Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| {
+            let path = e.pointer("/path/0").and_then(json::Value::as_str);
+            Some((path, e.get("message")?.as_str()?))
+        })
+}
+
+impl Error {
+    fn from_status(url: &str, err: &http::Error) -> Self {
+        let body = String::from_utf8_lossy(&err.body);
+        match err.status {
+            401 => Self::Unauthorized { url: url.into() },
+            403 if body.contains("insufficient_scope") => {
+                Self::InsufficientScope { url: url.into() }
+            }
+            429 => Self::RateLimited,
+            status @ 500..=599 => Self::Server { status },
+            status => Self::Other {
+                message: format!("GitLab responded with status {status}"),
+            },
+        }
+    }
+
+    fn from_message(url: &str, message: &str) -> Self {
+        let lower = message.to_lowercase();
+        if lower.contains("invalid token") || lower.contains("unauthorized") {
+            Self::Unauthorized { url: url.into() }
+        } else if lower.contains("insufficient_scope") || lower.contains("insufficient scope") {
+            Self::InsufficientScope { url: url.into() }
+        } else if lower.contains("rate limit") || lower.contains("too many requests") {
+            Self::RateLimited
+        } else {
+            Self::Other {
+                message: message.into(),
+            }
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<Self>() {
+            Ok(err) => err,
+            Err(err) => Self::Other {
+                message: format!("{err:#}"),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound { path } => write!(f, "`{path}` was not found"),
+            Self::Unauthorized { .. } => f.write_str("the GitLab token is invalid or expired"),
+            Self::InsufficientScope { .. } => {
+                f.write_str("the GitLab token is missing the `read_api` scope")
+            }
+            Self::RateLimited => f.write_str("rate limited by GitLab"),
+            Self::Server { status } => write!(f, "GitLab responded with status {status}"),
+            Self::Network { message } | Self::Other { message } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+const ISSUE_FRAGMENT: &str = r#"
+fragment IssueFields on Issue {
+    id
+    title
+    {description}
+    reference(full: true)
+    state
+    author {
+        name
+        username
+    }
+    assignees {
+        nodes {
+            name
+            username
+        }
+    }
+    createdAt
+    updatedAt
+    webUrl
+    labels {
+        nodes {
+            title
+        }
+    }
+    milestone {
+        title
+    }
+    confidential
+}
+"#;
+
+const MERGE_REQUEST_FRAGMENT: &str = r#"
+fragment MergeRequestFields on MergeRequest {
+    id
+    title
+    {description}
+    reference(full: true)
+    state
+    author {
+        name
+        username
+    }
+    assignees {
+        nodes {
+            name
+            username
+        }
+    }
+    reviewers {
+        nodes {
+            name
+            username
+        }
+    }
+    createdAt
+    updatedAt
+    webUrl
+    labels {
+        nodes {
+            title
+        }
+    }
+    milestone {
+        title
+    }
+    draft
+}
+"#;
+
+/// The selection of a project or group's issues or merge requests in a
+/// batched query, each aliased selection has its own variables.
+const CONNECTION: &str = r#"
+    {alias}: {scope}(fullPath: $path{i}) {
+        {field}({args}updatedAfter: $updatedAfter{i}, after: $after{i}) {
+            count
+            nodes {
+                ...{fragment}
             }
             pageInfo {
                 endCursor
@@ -149,36 +909,36 @@ query($project: ID!, $after: String) {
             }
         }
     }
+"#;
+
+const CURRENT_USER_QUERY: &str = r#"
+query {
+    currentUser {
+        username
+    }
 }
 "#;
-    fetch_and_parse(Query {
-        name,
-        project,
-        query,
-        page_info_ptr: "/data/project/issues/pageInfo",
-        nodes_ptr: "/data/project/issues/nodes",
-        parse_fn: parse_issue,
-    })
+
+const MY_ISSUES_QUERY: &str = r#"
+query($user: String!, $after: String) {
+    issues({filter}, state: opened, first: {first}, after: $after) {
+        nodes {
+            ...IssueFields
+        }
+        pageInfo {
+            endCursor
+            hasNextPage
+        }
+    }
 }
+"#;
 
-pub fn merge_requests(name: &str, project: &str) -> Result<Vec<MergeRequest>> {
-    let template = r#"
-query($project: ID!, $after: String) {
-    project(fullPath: $project) {
-        mergeRequests(state: opened, after: $after) {
+const MY_MERGE_REQUESTS_QUERY: &str = r#"
+query($after: String) {
+    currentUser {
+        {field}(state: opened, first: {first}, after: $after) {
             nodes {
-                title
-                author {
-                    name
-                    username
-                }
-                createdAt
-                webUrl
-                labels {
-                    nodes {
-                        title
-                    }
-                }
+                ...MergeRequestFields
             }
             pageInfo {
                 endCursor
@@ -188,48 +948,527 @@ query($project: ID!, $after: String) {
     }
 }
 "#;
-    fetch_and_parse(Query {
-        name,
-        project,
-        query: template,
-        page_info_ptr: "/data/project/mergeRequests/pageInfo",
-        nodes_ptr: "/data/project/mergeRequests/nodes",
-        parse_fn: parse_merge_request,
+
+const ITEM_QUERY: &str = r#"
+query($path: ID!, $iid: String!) {
+    project(fullPath: $path) {
+        item: {field}(iid: $iid) {
+            ...{fragment}
+        }
+    }
+}
+"#;
+
+/// Only the most recently updated items in states other than open are
+/// fetched.
+const MAX_PAGES: usize = 10;
+
+/// The number of items fetched per page, the most GitLab allows.
+const PAGE_SIZE: usize = 100;
+
+/// The longest a looked up item is cached for.
+const ITEM_TTL: Duration = Duration::from_secs(60);
+
+/// The maximum number of projects or groups fetched in a single request.
+const MAX_BATCH: usize = 5;
+
+/// Renders a query template for the given scope and state. A state of `None`
+/// fetches items in all states.
+fn render(template: &str, scope: Scope, state: Option<State>) -> String {
+    let field = match scope {
+        Scope::Project => "project",
+        Scope::Group => "group",
+    };
+    let mut args = format!("first: {PAGE_SIZE}, ");
+    match state {
+        Some(State::Opened) => args.push_str("state: opened, "),
+        Some(state) => args.push_str(&format!(
+            "state: {}, sort: UPDATED_DESC, ",
+            state_arg(state)
+        )),
+        None => args.push_str("state: all, sort: UPDATED_DESC, "),
+    }
+    if scope == Scope::Group {
+        args.push_str("includeSubgroups: true, ");
+    }
+    template.replace("{scope}", field).replace("{args}", &args)
+}
+
+fn state_arg(state: State) -> &'static str {
+    match state {
+        State::Opened => "opened",
+        State::Closed => "closed",
+        State::Merged => "merged",
+        State::Locked => "locked",
+    }
+}
+
+/// Returns the cache key for the issues or merge requests of a project or
+/// group in the state. The key doesn't depend on the command name so that
+/// every command for the same project shares its data. Open items are stored
+/// under the resource type and every other state in a subdirectory.
+fn cache_key(
+    instance: &Instance,
+    kind: Kind,
+    scope: Scope,
+    path: &str,
+    state: Option<State>,
+) -> String {
+    let scope = match scope {
+        Scope::Project => "project",
+        Scope::Group => "group",
+    };
+    let kind = match kind {
+        Kind::Issues => "issues",
+        Kind::MergeRequests => "merge-requests",
+    };
+    let key = format!("{}/{scope}/{path}/{kind}", instance.name);
+    match state {
+        Some(State::Opened) => key,
+        Some(state) => format!("{key}/{}", state_arg(state)),
+        None => format!("{key}/all"),
+    }
+}
+
+/// Returns a pointer to the timestamp that items in the state are sorted by.
+fn order_by(state: Option<State>) -> &'static str {
+    match state {
+        Some(State::Opened) => "/created_at",
+        _ => "/updated_at",
+    }
+}
+
+/// Returns the maximum number of pages to fetch for the state.
+fn max_pages(state: Option<State>) -> Option<usize> {
+    (state != Some(State::Opened)).then_some(MAX_PAGES)
+}
+
+pub fn issues(
+    instance: &Instance,
+    scope: Scope,
+    path: &str,
+    state: Option<State>,
+    ttl: Option<Duration>,
+    search: &query::Query,
+) -> Result<Loaded<Issue>> {
+    let source = Source {
+        instance,
+        kind: Kind::Issues,
+        scope,
+        path,
+        state,
+        ttl,
+    };
+    load(source, search)
+}
+
+pub fn merge_requests(
+    instance: &Instance,
+    scope: Scope,
+    path: &str,
+    state: Option<State>,
+    ttl: Option<Duration>,
+    search: &query::Query,
+) -> Result<Loaded<MergeRequest>> {
+    let source = Source {
+        instance,
+        kind: Kind::MergeRequests,
+        scope,
+        path,
+        state,
+        ttl,
+    };
+    load(source, search)
+}
+
+/// Returns the cached issues of a project or group without fetching them,
+/// `None` if there are none cached yet.
+pub fn cached_issues(
+    instance: &Instance,
+    scope: Scope,
+    path: &str,
+    state: Option<State>,
+    search: &query::Query,
+) -> Result<Option<Loaded<Issue>>> {
+    let source = Source {
+        instance,
+        kind: Kind::Issues,
+        scope,
+        path,
+        state,
+        ttl: None,
+    };
+    cached(source, search)
+}
+
+/// Returns the cached merge requests of a project or group without fetching
+/// them, `None` if there are none cached yet.
+pub fn cached_merge_requests(
+    instance: &Instance,
+    scope: Scope,
+    path: &str,
+    state: Option<State>,
+    search: &query::Query,
+) -> Result<Option<Loaded<MergeRequest>>> {
+    let source = Source {
+        instance,
+        kind: Kind::MergeRequests,
+        scope,
+        path,
+        state,
+        ttl: None,
+    };
+    cached(source, search)
+}
+
+/// Fetches the open issues on the instance that the current user has the
+/// given role on, see [`fetch_and_parse`]. Issues don't have reviewers so
+/// there are never any for that role.
+pub fn my_issues(
+    instance: &Instance,
+    role: Role,
+    ttl: Option<Duration>,
+    search: &query::Query,
+) -> Result<Option<Loaded<Issue>>> {
+    match my_issues_query(instance, role, ttl) {
+        Some(q) => fetch_and_parse(q, search),
+        None => Ok(Some(Loaded {
+            data: Vec::new(),
+            modified: SystemTime::now(),
+            error: None,
+            progress: None,
+        })),
+    }
+}
+
+fn my_issues_query(
+    instance: &Instance,
+    role: Role,
+    ttl: Option<Duration>,
+) -> Option<Query<'_, Issue>> {
+    let (filter, slug) = match role {
+        Role::ReviewRequested => return None,
+        Role::Assigned => ("assigneeUsernames: [$user]", "assigned-issues"),
+        Role::Authored => ("authorUsername: $user", "authored-issues"),
+    };
+    Some(Query {
+        instance,
+        name: format!("{}/me/{slug}", instance.name),
+        variables: Variables::default(),
+        // Issues can only be filtered by username.
+        current_user: true,
+        query: MY_ISSUES_QUERY
+            .replace("{filter}", filter)
+            .replace("{first}", &PAGE_SIZE.to_string())
+            + &fragment(Kind::Issues),
+        root_ptr: "/data/issues".into(),
+        page_info_ptr: "/data/issues/pageInfo".into(),
+        nodes_ptr: "/data/issues/nodes".into(),
+        ttl,
+        parse_fn: parse_issue,
     })
 }
 
+/// Fetches the open merge requests on the instance that the current user has
+/// the given role on, see [`fetch_and_parse`].
+pub fn my_merge_requests(
+    instance: &Instance,
+    role: Role,
+    ttl: Option<Duration>,
+    search: &query::Query,
+) -> Result<Option<Loaded<MergeRequest>>> {
+    fetch_and_parse(my_merge_requests_query(instance, role, ttl), search)
+}
+
+fn my_merge_requests_query(
+    instance: &Instance,
+    role: Role,
+    ttl: Option<Duration>,
+) -> Query<'_, MergeRequest> {
+    let (field, slug) = match role {
+        Role::ReviewRequested => (
+            "reviewRequestedMergeRequests",
+            "review-requested-merge-requests",
+        ),
+        Role::Assigned => ("assignedMergeRequests", "assigned-merge-requests"),
+        Role::Authored => ("authoredMergeRequests", "authored-merge-requests"),
+    };
+    let root = "/data/currentUser";
+    let ptr = format!("{root}/{field}");
+    Query {
+        instance,
+        name: format!("{}/me/{slug}", instance.name),
+        variables: Variables::default(),
+        current_user: false,
+        query: MY_MERGE_REQUESTS_QUERY
+            .replace("{field}", field)
+            .replace("{first}", &PAGE_SIZE.to_string())
+            + &fragment(Kind::MergeRequests),
+        root_ptr: root.into(),
+        page_info_ptr: format!("{ptr}/pageInfo"),
+        nodes_ptr: format!("{ptr}/nodes"),
+        ttl,
+        parse_fn: parse_merge_request,
+    }
+}
+
+/// Looks up a single issue in a project by its IID, whatever its state. See
+/// [`find`].
+pub fn issue(
+    instance: &Instance,
+    path: &str,
+    iid: u64,
+    ttl: Option<Duration>,
+    update_cache: bool,
+) -> Result<Option<Loaded<Issue>>> {
+    find(
+        instance,
+        Kind::Issues,
+        path,
+        iid,
+        ttl,
+        update_cache,
+        parse_issue,
+    )
+}
+
+/// Looks up a single merge request in a project by its IID, whatever its
+/// state. See [`find`].
+pub fn merge_request(
+    instance: &Instance,
+    path: &str,
+    iid: u64,
+    ttl: Option<Duration>,
+    update_cache: bool,
+) -> Result<Option<Loaded<MergeRequest>>> {
+    find(
+        instance,
+        Kind::MergeRequests,
+        path,
+        iid,
+        ttl,
+        update_cache,
+        parse_merge_request,
+    )
+}
+
+/// Looks up a single item in the cache, the data is empty if the item doesn't
+/// exist. Returns `None` if it hasn't been fetched yet. If `update_cache` is
+/// set then a missing or stale item is fetched in the background, so that it
+/// is there the next time. The item is refreshed at least every [`ITEM_TTL`],
+/// even if the TTL is manual, so that an item that didn't exist yet is found
+/// once it does.
+#[allow(clippy::too_many_arguments)]
+fn find<T>(
+    instance: &Instance,
+    kind: Kind,
+    path: &str,
+    iid: u64,
+    ttl: Option<Duration>,
+    update_cache: bool,
+    parse_fn: ParseFn<T>,
+) -> Result<Option<Loaded<T>>>
+where
+    T: Serialize + DeserializeOwned,
+{
+    #[derive(Serialize)]
+    struct Variables<'a> {
+        path: &'a str,
+        iid: String,
+    }
+
+    let token = instance.token()?;
+    let key = format!(
+        "{}/{iid}",
+        cache_key(instance, kind, Scope::Project, path, None)
+    );
+    let (field, name) = match kind {
+        Kind::Issues => ("issue", "IssueFields"),
+        Kind::MergeRequests => ("mergeRequest", "MergeRequestFields"),
+    };
+    let query = ITEM_QUERY
+        .replace("{field}", field)
+        .replace("{fragment}", name)
+        + &fragment(kind);
+    let checksum = {
+        use sha1::*;
+        let mut hasher = Sha1::new();
+        hash_instance(&mut hasher, instance);
+        hasher.update(key.as_bytes());
+        hasher.update(query.as_bytes());
+        hasher.finalize().into()
+    };
+
+    if !update_cache {
+        return cache::cached(checksum)?
+            .map(|data| data.try_map(parse_item))
+            .transpose();
+    }
+    let ttl = ttl.map_or(ITEM_TTL, |ttl| ttl.min(ITEM_TTL));
+    let interval = Some(ttl * http::throttle(&instance.url));
+    let data = cache::try_load::<_, Error>(&key, checksum, interval, || {
+        let update = match cache::begin(&key, checksum)? {
+            Some(update) => update,
+            None => return Ok(()),
+        };
+        let mut client = http::Client::new()?;
+        let variables = Variables {
+            path,
+            iid: iid.to_string(),
+        };
+        let result = fetch(&mut client, &instance.url, &query, &variables, token).and_then(
+            |resp| match resp.pointer("/data/project") {
+                None | Some(json::Value::Null) => Err(Error::NotFound { path: path.into() }.into()),
+                Some(project) => match project.get("item") {
+                    None | Some(json::Value::Null) => Ok(Vec::new()),
+                    Some(node) => Ok(vec![normalize(parse_fn, node.clone())?]),
+                },
+            },
+        );
+        match result {
+            // A single item is always stored inline, even with the SQLite
+            // backend.
+            Ok(items) => update.commit(json::to_value(Data {
+                updated_at: newest(&items),
+                items,
+            })?),
+            Err(err) => {
+                let err = Error::from(err);
+                update.fail(&err)?;
+                Err(err.into())
+            }
+        }
+    })?;
+    data.map(|data| data.try_map(parse_item)).transpose()
+}
+
+/// Deserializes a looked up item, which is always stored inline.
+fn parse_item<T: DeserializeOwned>(data: json::Value) -> Result<Vec<T>> {
+    if data.is_null() {
+        return Ok(Vec::new());
+    }
+    let data: Data<T> = json::from_value(data)?;
+    Ok(data.items)
+}
+
+/// Returns the checksums of every cache entry that the configured commands
+/// can use, any other entry is no longer needed.
+pub fn referenced() -> HashSet<[u8; 20]> {
+    let states = [
+        Some(State::Opened),
+        Some(State::Closed),
+        Some(State::Merged),
+        Some(State::Locked),
+        None,
+    ];
+    let roles = [Role::ReviewRequested, Role::Assigned, Role::Authored];
+    let mut checksums = HashSet::new();
+    for cmd in &CONFIG.commands {
+        for state in states {
+            let source = Source {
+                instance: &cmd.instance,
+                kind: cmd.kind,
+                scope: cmd.scope,
+                path: &cmd.path,
+                state,
+                ttl: cmd.ttl,
+            };
+            checksums.insert(source.checksum());
+        }
+    }
+    for instance in &CONFIG.instances {
+        for role in roles {
+            checksums.insert(my_merge_requests_query(instance, role, None).checksum());
+            checksums.extend(my_issues_query(instance, role, None).map(|q| q.checksum()));
+        }
+    }
+    checksums
+}
+
 fn parse_issue(value: json::Value) -> Result<Issue> {
+    let id = lookup(&value, "/id")?;
     let title = lookup(&value, "/title")?;
+    // The description is null if it was never set, and missing if it wasn't
+    // requested.
+    let description = description(&value);
+    let reference = lookup(&value, "/reference")?;
     let author = lookup(&value, "/author")?;
     let created_at: DateTime<chrono::Utc> = lookup::<String>(&value, "/createdAt")?.parse()?;
+    let updated_at: DateTime<chrono::Utc> = lookup::<String>(&value, "/updatedAt")?.parse()?;
     let url = lookup(&value, "/webUrl")?;
+    let state = lookup(&value, "/state")?;
     let labels = lookup_list(&value, "/labels/nodes", "/title")?;
     let assignees = lookup_list(&value, "/assignees/nodes", "")?;
+    let milestone = milestone(&value);
+    let confidential = lookup(&value, "/confidential")?;
     Ok(Issue {
+        id,
         title,
+        description,
+        reference,
         url,
+        state,
         author,
         assignees,
         created_at,
+        updated_at,
         labels,
+        milestone,
+        confidential,
     })
 }
 
 fn parse_merge_request(value: json::Value) -> Result<MergeRequest> {
+    let id = lookup(&value, "/id")?;
     let title = lookup(&value, "/title")?;
+    // The description is null if it was never set, and missing if it wasn't
+    // requested.
+    let description = description(&value);
+    let reference = lookup(&value, "/reference")?;
     let author = lookup(&value, "/author")?;
     let created_at: DateTime<chrono::Utc> = lookup::<String>(&value, "/createdAt")?.parse()?;
+    let updated_at: DateTime<chrono::Utc> = lookup::<String>(&value, "/updatedAt")?.parse()?;
     let url = lookup(&value, "/webUrl")?;
+    let state = lookup(&value, "/state")?;
     let labels = lookup_list(&value, "/labels/nodes", "/title")?;
+    let assignees = lookup_list(&value, "/assignees/nodes", "")?;
+    let reviewers = lookup_list(&value, "/reviewers/nodes", "")?;
+    let milestone = milestone(&value);
+    let draft = lookup(&value, "/draft")?;
     Ok(MergeRequest {
+        id,
         title,
+        description,
+        reference,
         url,
+        state,
         author,
+        assignees,
+        reviewers,
         created_at,
+        updated_at,
         labels,
+        milestone,
+        draft,
     })
 }
 
+/// Returns the title of the milestone, which is null if there isn't one.
+fn description(value: &json::Value) -> String {
+    value
+        .pointer("/description")
+        .and_then(json::Value::as_str)
+        .unwrap_or_default()
+        .to_owned()
+}
+
+fn milestone(value: &json::Value) -> Option<String> {
+    value
+        .pointer("/milestone/title")
+        .and_then(json::Value::as_str)
+        .map(str::to_owned)
+}
+
 fn lookup<T>(value: &json::Value, ptr: &str) -> Result<T>
 where
     T: DeserializeOwned,
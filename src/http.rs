@@ -0,0 +1,250 @@
+use std::fmt;
+use std::fs;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+
+use crate::cache;
+use crate::config::CONFIG;
+
+/// The number of times a request is retried before giving up.
+const MAX_RETRIES: u32 = 4;
+
+/// The delay before the first retry, doubled for every retry after that.
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// The longest we are willing to wait for a `Retry-After` header.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// An unsuccessful HTTP response.
+#[derive(Debug)]
+pub struct Error {
+    pub status: u32,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct Response {
+    status: u32,
+    headers: Vec<String>,
+    body: Vec<u8>,
+}
+
+/// The rate limit state last reported by a GitLab instance.
+#[derive(Debug, Deserialize, Serialize)]
+struct RateLimit {
+    limit: u64,
+    remaining: u64,
+    reset: SystemTime,
+}
+
+/// An HTTP client that reuses its connection across requests.
+pub struct Client {
+    easy: curl::easy::Easy,
+}
+
+impl Client {
+    pub fn new() -> Result<Self> {
+        let mut easy = curl::easy::Easy::new();
+        let opts = &CONFIG.http;
+        easy.connect_timeout(opts.connect_timeout)?;
+        easy.timeout(opts.timeout)?;
+        if let Some(proxy) = &opts.proxy {
+            easy.proxy(proxy)?;
+        }
+        if let Some(path) = &opts.ca_bundle {
+            easy.cainfo(path)?;
+        }
+        if let Some(path) = &opts.client_cert {
+            easy.ssl_cert(path)?;
+        }
+        if let Some(path) = &opts.client_key {
+            easy.ssl_key(path)?;
+        }
+        // Not every build of libcurl supports HTTP/2, in which case HTTP/1.1
+        // is used.
+        let _ = easy.http_version(curl::easy::HttpVersion::V2TLS);
+        // An empty string enables every encoding that libcurl supports.
+        easy.accept_encoding("")?;
+        easy.follow_location(true)?;
+        easy.post(true)?;
+        Ok(Self { easy })
+    }
+
+    /// Sends a POST request with the given JSON body, retrying rate limited
+    /// requests and transient server errors with backoff.
+    pub fn post(&mut self, url: &str, token: &str, body: &[u8]) -> Result<Vec<u8>> {
+        let mut retries = 0;
+        loop {
+            let delay = match self.send(url, token, body) {
+                Ok(resp) => {
+                    if let Some(rl) = resp.rate_limit() {
+                        if let Err(err) = rl.save(url) {
+                            log::warn!("failed to save rate limit: {err:#}");
+                        }
+                    }
+                    match resp.status {
+                        200..=299 => return Ok(resp.body),
+                        429 | 500 | 502 | 503 | 504 if retries < MAX_RETRIES => resp.retry_after(),
+                        status => {
+                            let body = resp.body;
+                            return Err(Error { status, body }.into());
+                        }
+                    }
+                }
+                Err(err) if is_transient(&err) && retries < MAX_RETRIES => None,
+                Err(err) => return Err(err.into()),
+            };
+            let delay = delay.unwrap_or_else(|| backoff(retries)).min(MAX_DELAY);
+            log::warn!(
+                "request to {url} failed, retrying in {:.1}s",
+                delay.as_secs_f32()
+            );
+            thread::sleep(delay);
+            retries += 1;
+        }
+    }
+
+    fn send(&mut self, url: &str, token: &str, body: &[u8]) -> Result<Response, curl::Error> {
+        let mut resp = Response::default();
+        let mut data = body;
+
+        let easy = &mut self.easy;
+        easy.http_headers({
+            let mut hl = curl::easy::List::new();
+            hl.append(&format!("Authorization: Bearer {token}"))?;
+            hl.append("Content-Type: application/json")?;
+            hl
+        })?;
+        easy.post_field_size(body.len() as u64)?;
+        easy.url(url)?;
+
+        {
+            let mut t = easy.transfer();
+            t.read_function(|into| Ok(data.read(into).unwrap()))?;
+            t.header_function(|header| {
+                resp.headers
+                    .push(String::from_utf8_lossy(header).trim().to_owned());
+                true
+            })?;
+            t.write_function(|data| {
+                resp.body.extend_from_slice(data);
+                Ok(data.len())
+            })?;
+            t.perform()?;
+        }
+
+        resp.status = easy.response_code()?;
+        log::info!(
+            "POST {url} {} in {}ms (connect {}ms, tls {}ms, first byte {}ms, {} bytes)",
+            resp.status,
+            easy.total_time()?.as_millis(),
+            easy.connect_time()?.as_millis(),
+            easy.appconnect_time()?.as_millis(),
+            easy.starttransfer_time()?.as_millis(),
+            resp.body.len(),
+        );
+        Ok(resp)
+    }
+}
+
+/// Returns how many times slower the cache for the given instance should be
+/// refreshed based on how much of the rate limit is left.
+pub fn throttle(url: &str) -> u32 {
+    let rl = match RateLimit::load(url) {
+        Some(rl) if rl.reset > SystemTime::now() && rl.limit > 0 => rl,
+        _ => return 1,
+    };
+    match rl.remaining * 100 / rl.limit {
+        0..=9 => 8,
+        10..=24 => 4,
+        25..=49 => 2,
+        _ => 1,
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "request failed with status {}", self.status)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find_map(|h| {
+            let (k, v) = h.split_once(':')?;
+            k.trim().eq_ignore_ascii_case(name).then(|| v.trim())
+        })
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        let secs = self.header("Retry-After")?.parse().ok()?;
+        Some(Duration::from_secs(secs))
+    }
+
+    fn rate_limit(&self) -> Option<RateLimit> {
+        let limit = self.header("RateLimit-Limit")?.parse().ok()?;
+        let remaining = self.header("RateLimit-Remaining")?.parse().ok()?;
+        let reset = self.header("RateLimit-Reset")?.parse().ok()?;
+        Some(RateLimit {
+            limit,
+            remaining,
+            reset: UNIX_EPOCH + Duration::from_secs(reset),
+        })
+    }
+}
+
+impl RateLimit {
+    fn path(url: &str) -> PathBuf {
+        cache::DIR
+            .join("ratelimit")
+            .join(format!("{}.json", host(url)))
+    }
+
+    fn load(url: &str) -> Option<Self> {
+        let data = fs::read(Self::path(url)).ok()?;
+        json::from_slice(&data).ok()
+    }
+
+    fn save(&self, url: &str) -> Result<()> {
+        let path = Self::path(url);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, json::to_vec(self)?)?;
+        Ok(())
+    }
+}
+
+/// Whether the error is likely to go away if the request is retried.
+fn is_transient(err: &curl::Error) -> bool {
+    err.is_couldnt_connect()
+        || err.is_couldnt_resolve_host()
+        || err.is_operation_timedout()
+        || err.is_send_error()
+        || err.is_recv_error()
+        || err.is_got_nothing()
+        || err.is_partial_file()
+}
+
+/// Returns an exponential backoff delay with jitter for the given retry.
+fn backoff(retry: u32) -> Duration {
+    let delay = BASE_DELAY * 2u32.pow(retry);
+    // A cheap source of randomness is good enough to avoid every updater
+    // retrying in lockstep.
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    delay.mul_f64(0.5 + f64::from(nanos % 1000) / 2000.0)
+}
+
+fn host(url: &str) -> &str {
+    let url = url.split_once("://").map(|(_, u)| u).unwrap_or(url);
+    url.split('/').next().unwrap_or(url)
+}
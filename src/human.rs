@@ -31,3 +31,29 @@ pub fn format_ago(d: Duration) -> Cow<'static, str> {
         }
     }
 }
+
+pub fn format_size(bytes: u64) -> String {
+    match bytes {
+        x if x < 1024 => format!("{x} B"),
+        x if x < 1024 * 1024 => format!("{:.1} KB", x as f32 / 1024.),
+        x => format!("{:.1} MB", x as f32 / 1024. / 1024.),
+    }
+}
+
+/// Parses a duration like `30s`, `10m`, `2h`, `7d`, `2w` or `1y`. Returns
+/// `None` if the unit is missing or unknown, or if the duration is too large.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let i = s.find(|c: char| !c.is_ascii_digit())?;
+    let (n, unit) = s.split_at(i);
+    let n: u64 = n.parse().ok()?;
+    let secs = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "y" => 365 * 24 * 60 * 60,
+        _ => return None,
+    };
+    n.checked_mul(secs).map(Duration::from_secs)
+}
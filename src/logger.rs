@@ -53,6 +53,22 @@ impl Logger {
     }
 }
 
+/// Removes the log files written by other versions of the workflow.
+pub fn remove_old() -> Result<()> {
+    let prefix = concat!(env!("CARGO_PKG_NAME"), "-");
+    for entry in fs::read_dir(&*cache::DIR)? {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+        if name.starts_with(prefix) && name.ends_with(".log") && name != LOG_FILENAME {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
 pub fn init() -> Result<()> {
     log::set_logger(&*LOGGER)?;
     log::set_max_level(log::LevelFilter::Info);
@@ -1,47 +1,91 @@
 mod cache;
 mod config;
+mod fuzzy;
 mod gitlab;
+mod http;
 mod human;
 mod logger;
+mod query;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 
 use std::cmp::Reverse;
+use std::collections::HashSet;
 use std::env;
+use std::fmt;
 use std::io;
 use std::iter;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Result;
 use chrono::DateTime;
-use powerpack::Item;
-use serde::Deserialize;
+use powerpack::{Icon, Item, Key, Modifier};
+use serde::{Deserialize, Serialize};
 
-use crate::config::{Command, Kind, CONFIG};
+use crate::config::{Command, Instance, Kind, Scope, CONFIG};
+use crate::query::{Filter, Query, Term};
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Issue {
+    id: String,
     title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    description: String,
+    reference: String,
     author: User,
     assignees: Vec<User>,
     url: String,
+    state: State,
     created_at: DateTime<chrono::Utc>,
+    updated_at: DateTime<chrono::Utc>,
     labels: Vec<String>,
+    milestone: Option<String>,
+    confidential: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct MergeRequest {
+    id: String,
     title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    description: String,
+    reference: String,
     author: User,
+    assignees: Vec<User>,
+    reviewers: Vec<User>,
     url: String,
+    state: State,
     created_at: DateTime<chrono::Utc>,
+    updated_at: DateTime<chrono::Utc>,
     labels: Vec<String>,
+    milestone: Option<String>,
+    draft: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct User {
     name: String,
     username: String,
 }
 
+/// The state of an issue or merge request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Opened,
+    Closed,
+    Merged,
+    Locked,
+}
+
+/// The relationship of the current user to an issue or merge request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    ReviewRequested,
+    Assigned,
+    Authored,
+}
+
 impl Issue {
     fn ours_first(&self) -> Reverse<(bool, bool)> {
         let is_ours = CONFIG
@@ -57,71 +101,179 @@ impl Issue {
         Reverse(is_ours)
     }
 
-    fn matches(&self, query: &str) -> bool {
-        query.split_whitespace().all(|q| {
-            if let Some(q) = q.strip_prefix('~') {
-                self.labels
+    /// Scores how relevant the issue is to the query, returns `None` if it
+    /// doesn't match.
+    fn score(&self, query: &Query, now: DateTime<chrono::Utc>) -> Option<u32> {
+        let score = score_terms(
+            query,
+            &self.title,
+            &self.description,
+            |filter| match filter {
+                Filter::Label(q) => self
+                    .labels
                     .iter()
-                    .any(|label| label.to_lowercase().contains(q))
-            } else if let Some(q) = q.strip_prefix('@') {
-                self.author.matches(q) || self.assignees.iter().any(|a| a.matches(q))
-            } else {
-                self.title.to_lowercase().contains(q)
-            }
-        })
+                    .any(|label| label.to_lowercase().contains(q)),
+                Filter::User(q) => {
+                    self.author.matches(q) || self.assignees.iter().any(|a| a.matches(q))
+                }
+                Filter::Milestone(q) => matches_milestone(self.milestone.as_deref(), q),
+                Filter::Iid(iid) => iid_of(&self.reference) == Some(*iid),
+                Filter::Draft => false,
+                Filter::Confidential => self.confidential,
+                Filter::Created(age) => age.matches(elapsed(self.created_at, now)),
+                Filter::Updated(age) => age.matches(elapsed(self.updated_at, now)),
+                Filter::Word(_) | Filter::Phrase(_) => true,
+            },
+        )?;
+        let Reverse(ours) = self.ours_first();
+        Some(fuzzy::rank(
+            score,
+            elapsed(self.updated_at, now),
+            ours != Default::default(),
+        ))
+    }
+
+    fn project(&self) -> &str {
+        project_of(&self.reference)
     }
 
-    fn into_item(self, now: chrono::DateTime<chrono::Utc>) -> Item {
+    fn into_item(self, now: chrono::DateTime<chrono::Utc>, prefix: Option<String>) -> Item {
         let Self { title, url, .. } = self;
         let ago = human::format_ago((now - self.created_at).to_std().unwrap());
-        let subtitle = if self.assignees.is_empty() {
+        let mut subtitle = if self.assignees.is_empty() {
             let author = self.author.name;
             format!("{ago}, authored by {author}")
         } else {
-            let assignees = self
-                .assignees
-                .iter()
-                .map(|u| u.name.as_str())
-                .collect::<Vec<_>>()
-                .join(", ");
+            let assignees = names(&self.assignees);
             format!("{ago}, assigned to {assignees}")
         };
+        if self.state != State::Opened {
+            subtitle = format!("{} · {subtitle}", self.state);
+        }
+        if let Some(prefix) = prefix {
+            subtitle = format!("{prefix} · {subtitle}");
+        }
         let arg = format!("{url};{title}");
-        powerpack::Item::new(title).subtitle(subtitle).arg(arg)
+        let item = powerpack::Item::new(title).subtitle(subtitle).arg(arg);
+        match self.state.icon() {
+            Some(icon) => item.icon(icon),
+            None => item,
+        }
     }
 }
 
 impl MergeRequest {
-    fn ours_first(&self) -> Reverse<bool> {
+    fn ours_first(&self) -> Reverse<(bool, bool, bool)> {
         let is_ours = CONFIG
             .user
             .as_ref()
-            .map(|u| self.author.matches(u))
-            .unwrap_or(false);
+            .map(|u| {
+                (
+                    self.reviewers.iter().any(|r| r.matches(u)),
+                    self.assignees.iter().any(|a| a.matches(u)),
+                    self.author.matches(u),
+                )
+            })
+            .unwrap_or((false, false, false));
         Reverse(is_ours)
     }
 
-    fn matches(&self, query: &str) -> bool {
-        query.split_whitespace().all(|q| {
-            if let Some(q) = q.strip_prefix('~') {
-                self.labels
+    /// Scores how relevant the merge request is to the query, returns `None`
+    /// if it doesn't match.
+    fn score(&self, query: &Query, now: DateTime<chrono::Utc>) -> Option<u32> {
+        let score = score_terms(
+            query,
+            &self.title,
+            &self.description,
+            |filter| match filter {
+                Filter::Label(q) => self
+                    .labels
                     .iter()
-                    .any(|label| label.to_lowercase().contains(q))
-            } else if let Some(q) = q.strip_prefix('@') {
-                self.author.matches(q)
-            } else {
-                self.title.to_lowercase().contains(q)
-            }
-        })
+                    .any(|label| label.to_lowercase().contains(q)),
+                Filter::User(q) => {
+                    self.author.matches(q)
+                        || self.assignees.iter().any(|a| a.matches(q))
+                        || self.reviewers.iter().any(|r| r.matches(q))
+                }
+                Filter::Milestone(q) => matches_milestone(self.milestone.as_deref(), q),
+                Filter::Iid(iid) => iid_of(&self.reference) == Some(*iid),
+                Filter::Draft => self.draft,
+                Filter::Confidential => false,
+                Filter::Created(age) => age.matches(elapsed(self.created_at, now)),
+                Filter::Updated(age) => age.matches(elapsed(self.updated_at, now)),
+                Filter::Word(_) | Filter::Phrase(_) => true,
+            },
+        )?;
+        let Reverse(ours) = self.ours_first();
+        Some(fuzzy::rank(
+            score,
+            elapsed(self.updated_at, now),
+            ours != Default::default(),
+        ))
+    }
+
+    fn project(&self) -> &str {
+        project_of(&self.reference)
     }
 
-    fn into_item(self, now: chrono::DateTime<chrono::Utc>) -> Item {
+    fn into_item(self, now: chrono::DateTime<chrono::Utc>, prefix: Option<String>) -> Item {
         let Self { title, url, .. } = self;
         let ago = human::format_ago((now - self.created_at).to_std().unwrap());
         let author = self.author.name;
-        let subtitle = format!("{ago} by {author}");
+        let mut subtitle = if !self.reviewers.is_empty() {
+            let reviewers = names(&self.reviewers);
+            format!("{ago} by {author}, waiting on {reviewers}")
+        } else if !self.assignees.is_empty() {
+            let assignees = names(&self.assignees);
+            format!("{ago} by {author}, assigned to {assignees}")
+        } else {
+            format!("{ago} by {author}")
+        };
+        if self.state != State::Opened {
+            subtitle = format!("{} · {subtitle}", self.state);
+        }
+        if let Some(prefix) = prefix {
+            subtitle = format!("{prefix} · {subtitle}");
+        }
         let arg = format!("{url};{title}");
-        powerpack::Item::new(title).subtitle(subtitle).arg(arg)
+        let item = powerpack::Item::new(title).subtitle(subtitle).arg(arg);
+        match self.state.icon() {
+            Some(icon) => item.icon(icon),
+            None => item,
+        }
+    }
+}
+
+impl State {
+    /// Returns the icon for the state, open items use the workflow icon.
+    fn icon(&self) -> Option<Icon> {
+        match self {
+            Self::Opened => None,
+            Self::Closed => Some(Icon::with_image("icons/closed.png")),
+            Self::Merged => Some(Icon::with_image("icons/merged.png")),
+            Self::Locked => Some(Icon::with_image("icons/locked.png")),
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Opened => f.write_str("Open"),
+            Self::Closed => f.write_str("Closed"),
+            Self::Merged => f.write_str("Merged"),
+            Self::Locked => f.write_str("Locked"),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReviewRequested => f.write_str("Review requested"),
+            Self::Assigned => f.write_str("Assigned"),
+            Self::Authored => f.write_str("Authored"),
+        }
     }
 }
 
@@ -131,57 +283,363 @@ impl User {
     }
 }
 
+/// Returns a comma separated list of the users' names.
+fn names(users: &[User]) -> String {
+    users
+        .iter()
+        .map(|u| u.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Scores the words and phrases of the query against the title and the
+/// description, which is only fetched if the backend indexes it. Returns
+/// `None` if any term doesn't match. The other filters are checked with the
+/// given function.
+fn score_terms(
+    query: &Query,
+    title: &str,
+    description: &str,
+    matches: impl Fn(&Filter) -> bool,
+) -> Option<u32> {
+    let mut score = 0;
+    for Term { negated, filter } in &query.terms {
+        match filter {
+            Filter::Word(q) if !negated => score += fuzzy::score_item(q, title, description)?,
+            Filter::Word(q) | Filter::Phrase(q) => {
+                let found = title.to_lowercase().contains(q.as_str())
+                    || description.to_lowercase().contains(q.as_str());
+                if found == *negated {
+                    return None;
+                }
+                if found {
+                    score += fuzzy::score_item(q, title, description).unwrap_or_default();
+                }
+            }
+            filter => {
+                if matches(filter) == *negated {
+                    return None;
+                }
+            }
+        }
+    }
+    Some(score)
+}
+
+fn matches_milestone(milestone: Option<&str>, query: &str) -> bool {
+    milestone.is_some_and(|m| m.to_lowercase().contains(query))
+}
+
+/// Returns how long ago the given time was.
+fn elapsed(time: DateTime<chrono::Utc>, now: DateTime<chrono::Utc>) -> Duration {
+    (now - time).to_std().unwrap_or_default()
+}
+
+/// Returns the IID from a full reference like `group/project#123`.
+fn iid_of(reference: &str) -> Option<u64> {
+    reference.rsplit_once(['#', '!'])?.1.parse().ok()
+}
+
+/// Returns the project path from a full reference like `group/project#123`.
+fn project_of(reference: &str) -> &str {
+    reference
+        .rsplit_once(['#', '!'])
+        .map(|(p, _)| p)
+        .unwrap_or(reference)
+}
+
 impl Command {
     fn to_item(&self) -> Item {
-        let Self { name, project, .. } = self;
-        let subtitle = match self.kind {
-            Kind::Issues => format!("Search issues in {project}"),
-            Kind::MergeRequests => format!("Search merge requests in {project}"),
+        let Self {
+            name,
+            path,
+            instance,
+            ..
+        } = self;
+        let subtitle = match (self.kind, self.scope) {
+            (Kind::Issues, Scope::Project) => format!("Search issues in {path}"),
+            (Kind::MergeRequests, Scope::Project) => format!("Search merge requests in {path}"),
+            (Kind::Issues, Scope::Group) => format!("Search issues in the {path} group"),
+            (Kind::MergeRequests, Scope::Group) => {
+                format!("Search merge requests in the {path} group")
+            }
         };
         Item::new(&self.name)
             .subtitle(subtitle)
-            .arg(format!("https://gitlab.com/{project};{name}"))
+            .arg(format!("{}/{path};{name}", instance.url))
             .autocomplete(format!("{name} "))
     }
 
     fn exec(&self, query: &str) -> Result<Vec<Item>> {
         let now = chrono::Utc::now();
+        let show_project = self.scope == Scope::Group;
+        let raw = query;
+        let query = match query::parse(query) {
+            Ok(query) => query,
+            Err(err) => return Ok(vec![query_error_item(&err)]),
+        };
+        let state = query.state;
+        let ttl = refresh_ttl(&query, self.ttl);
 
-        let items = match self.kind {
+        if self.kind == Kind::Issues && state == Some(State::Merged) {
+            let item = Item::new("Issues can't be merged")
+                .subtitle("Use `is:closed` to search closed issues")
+                .valid(false);
+            return Ok(vec![item]);
+        }
+        if let Some(item) = unsupported_filter_item(self.kind, &query) {
+            return Ok(vec![item]);
+        }
+
+        // The item that the query refers to directly is listed first.
+        let jump = match self.jump(raw, ttl, now) {
+            Ok(jump) => jump,
+            Err(err) => {
+                log::warn!("failed to look up `{raw}`: {err:#}");
+                None
+            }
+        };
+        let jumped = |url: &str| jump.as_ref().is_some_and(|(u, _)| u == url);
+
+        let mut items = Vec::new();
+        match self.kind {
             Kind::Issues => {
-                let mut items = Vec::new();
-                if let Some(query) = query.strip_prefix('/') {
+                let issues =
+                    gitlab::issues(&self.instance, self.scope, &self.path, state, ttl, &query)?;
+                items.extend(status_item(issues.modified, issues.error.as_ref()));
+                items.extend(loading_item(issues.progress));
+                if let Some(query) = raw.trim().strip_prefix('/').filter(|_| CONFIG.shortcuts) {
                     for (cmd, f) in EXTRAS {
                         if cmd.starts_with(query) {
-                            items.push(f(&self.project));
+                            items.extend(f(self));
                         }
                     }
                 }
-                let issues = {
-                    let mut issues = gitlab::issues(&self.name, &self.project)?;
-                    issues.sort_by_key(Issue::ours_first);
+                let issues = rank(issues.data, |i| i.score(&query, now), Issue::ours_first);
+                items.extend(
                     issues
                         .into_iter()
-                        .filter_map(|i| i.matches(query).then(|| i.into_item(now)))
-                };
-                items.extend(issues);
-                items
+                        .filter(|(_, i)| !jumped(&i.url))
+                        .map(|(_, i)| {
+                            let prefix = show_project.then(|| i.project().to_owned());
+                            i.into_item(now, prefix)
+                        }),
+                );
             }
             Kind::MergeRequests => {
-                let mut merge_requests = gitlab::merge_requests(&self.name, &self.project)?;
-                merge_requests.sort_by_key(MergeRequest::ours_first);
-                merge_requests
-                    .into_iter()
-                    .filter_map(|m| m.matches(query).then(|| m.into_item(now)))
-                    .collect()
+                let merge_requests = gitlab::merge_requests(
+                    &self.instance,
+                    self.scope,
+                    &self.path,
+                    state,
+                    ttl,
+                    &query,
+                )?;
+                items.extend(status_item(
+                    merge_requests.modified,
+                    merge_requests.error.as_ref(),
+                ));
+                items.extend(loading_item(merge_requests.progress));
+                let merge_requests = rank(
+                    merge_requests.data,
+                    |m| m.score(&query, now),
+                    MergeRequest::ours_first,
+                );
+                items.extend(
+                    merge_requests
+                        .into_iter()
+                        .filter(|(_, m)| !jumped(&m.url))
+                        .map(|(_, m)| {
+                            let prefix = show_project.then(|| m.project().to_owned());
+                            m.into_item(now, prefix)
+                        }),
+                );
             }
-        };
+        }
+        if let Some((_, item)) = jump {
+            items.insert(0, item);
+        }
 
         Ok(items)
     }
+
+    /// Looks up the issue or merge request that the query refers to by its
+    /// IID, full reference, or web URL. It is fetched on its own so that it
+    /// is found even if it's not in the cached list, for example because it
+    /// is closed. Nothing waits for the fetch, the item is listed once it has
+    /// been fetched. An IID on its own is only fetched once the user stops
+    /// typing, so that every prefix of it isn't. Returns the URL of the item
+    /// and the item.
+    fn jump(
+        &self,
+        query: &str,
+        ttl: Option<Duration>,
+        now: DateTime<chrono::Utc>,
+    ) -> Result<Option<(String, Item)>> {
+        let reference = match query::parse_reference(query) {
+            Some(reference) => reference,
+            None => return Ok(None),
+        };
+        let update_cache = reference.is_complete()
+            || cache::settled(&format!("{} {query}", self.name), SETTLE_DURATION)?;
+        let Target {
+            instance,
+            path,
+            kind,
+            iid,
+        } = match self.resolve(reference, &CONFIG.instances) {
+            Some(target) => target,
+            None => return Ok(None),
+        };
+        let prefix = (self.scope == Scope::Group || path != self.path).then(|| path.clone());
+        let jump = match kind {
+            Kind::Issues => gitlab::issue(instance, &path, iid, ttl, update_cache)?
+                .and_then(|loaded| loaded.data.into_iter().next())
+                .map(|i| (i.url.clone(), i.into_item(now, prefix))),
+            Kind::MergeRequests => gitlab::merge_request(instance, &path, iid, ttl, update_cache)?
+                .and_then(|loaded| loaded.data.into_iter().next())
+                .map(|m| (m.url.clone(), m.into_item(now, prefix))),
+        };
+        Ok(jump)
+    }
+
+    /// Resolves the instance, project and kind of the item that a reference
+    /// refers to. A URL must be on one of the given instances, other
+    /// references are on the instance of the command.
+    fn resolve<'a>(
+        &'a self,
+        reference: query::Reference,
+        instances: &'a [Instance],
+    ) -> Option<Target<'a>> {
+        let (instance, path) = match (reference.project_url, reference.path) {
+            // The longest URL wins in case an instance is on a sub-path of
+            // another one.
+            (Some(url), _) => instances
+                .iter()
+                .filter_map(|i| {
+                    let path = url.strip_prefix(&i.url.to_lowercase())?.strip_prefix('/')?;
+                    Some((i, path.to_owned()))
+                })
+                .max_by_key(|(i, _)| i.url.len())?,
+            (None, Some(path)) => (&self.instance, path),
+            (None, None) if self.scope == Scope::Project => (&self.instance, self.path.clone()),
+            // An IID on its own is ambiguous in a group.
+            (None, None) => return None,
+        };
+        Some(Target {
+            instance,
+            path,
+            kind: reference.kind.unwrap_or(self.kind),
+            iid: reference.iid,
+        })
+    }
+}
+
+/// How long a query must stay the same before an IID in it is looked up.
+const SETTLE_DURATION: Duration = Duration::from_millis(500);
+
+/// The item that a reference refers to.
+#[derive(Debug)]
+struct Target<'a> {
+    instance: &'a Instance,
+    path: String,
+    kind: Kind,
+    iid: u64,
+}
+
+/// Filters the items that match the query and sorts them by relevance, items
+/// that are equally relevant are sorted by how much they involve the user.
+/// Returns each item along with its score.
+fn rank<T, K: Ord>(
+    items: Vec<T>,
+    score: impl Fn(&T) -> Option<u32>,
+    ours_first: impl Fn(&T) -> K,
+) -> Vec<(u32, T)> {
+    let mut items: Vec<_> = items
+        .into_iter()
+        .filter_map(|item| Some((score(&item)?, item)))
+        .collect();
+    items.sort_by_key(|(score, item)| (Reverse(*score), ours_first(item)));
+    items
+}
+
+/// How recently data must have been refreshed for `cache:refresh` to not
+/// refresh it again, so that it doesn't refresh on every keystroke.
+const REFRESHED: Duration = Duration::from_secs(10);
+
+/// Returns the TTL to load data with, which is short if the query asks to
+/// refresh the cache.
+fn refresh_ttl(query: &Query, ttl: Option<Duration>) -> Option<Duration> {
+    if query.refresh {
+        Some(ttl.map_or(REFRESHED, |ttl| ttl.min(REFRESHED)))
+    } else {
+        ttl
+    }
+}
+
+/// Returns an item saying how old the data is if the last refresh failed.
+fn status_item(modified: SystemTime, error: Option<&gitlab::Error>) -> Option<Item> {
+    let error = error?;
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default();
+    let status = match error {
+        gitlab::Error::Network { .. } => "Offline",
+        _ => "Refresh failed",
+    };
+    let item = Item::new(format!(
+        "{status} — showing data from {}",
+        human::format_ago(age)
+    ))
+    .subtitle(format!("Last error: {error}"))
+    .valid(false);
+    Some(item)
 }
 
-type ItemFn = fn(&str) -> Item;
+/// Returns an item saying how much is left to fetch if the data is still
+/// being fetched for the first time. The workflow is rerun so the results are
+/// updated as more pages are fetched.
+fn loading_item(progress: Option<cache::Progress>) -> Option<Item> {
+    let cache::Progress { pages, total } = progress?;
+    let title = match total {
+        Some(total) => format!("Loading {total} pages…"),
+        None => "Loading…".to_owned(),
+    };
+    let subtitle = match pages {
+        0 => "Waiting for the first page".to_owned(),
+        1 => "Showing the first page so far".to_owned(),
+        pages => format!("Showing the first {pages} pages so far"),
+    };
+    Some(Item::new(title).subtitle(subtitle).valid(false))
+}
+
+/// Returns an item explaining that the query has a qualifier that doesn't
+/// apply to the kind of items the command lists, which would match nothing.
+fn unsupported_filter_item(kind: Kind, query: &Query) -> Option<Item> {
+    // Negated, the qualifier matches every item instead.
+    let mut terms = query.terms.iter().filter(|t| !t.negated);
+    let (title, subtitle) = terms.find_map(|t| match (kind, &t.filter) {
+        (Kind::Issues, Filter::Draft) => Some((
+            "Issues can't be drafts",
+            "Use `is:draft` to search merge requests",
+        )),
+        (Kind::MergeRequests, Filter::Confidential) => Some((
+            "Merge requests can't be confidential",
+            "Use `is:confidential` to search issues",
+        )),
+        _ => None,
+    })?;
+    Some(Item::new(title).subtitle(subtitle).valid(false))
+}
+
+/// Returns an item explaining why the query couldn't be parsed.
+fn query_error_item(err: &query::Error) -> Item {
+    Item::new(format!("Invalid query: {err}"))
+        .subtitle(err.hint())
+        .valid(false)
+}
+
+type ItemFn = fn(&Command) -> Option<Item>;
 
 const EXTRAS: &[(&str, ItemFn)] = &[
     ("new", new_item),
@@ -189,36 +647,422 @@ const EXTRAS: &[(&str, ItemFn)] = &[
     ("list", list_item),
 ];
 
-fn new_item(project: &str) -> Item {
-    Item::new("/new")
-        .subtitle(format!("Create a new issue in {project}"))
-        .arg(format!("https://gitlab.com/{project}/issues/new"))
+fn new_item(cmd: &Command) -> Option<Item> {
+    let Command { path, instance, .. } = cmd;
+    // Issues can only be created in a project.
+    (cmd.scope == Scope::Project).then(|| {
+        Item::new("/new")
+            .subtitle(format!("Create a new issue in {path}"))
+            .arg(format!("{}/{path}/issues/new", instance.url))
+    })
+}
+
+fn boards_item(cmd: &Command) -> Option<Item> {
+    let Command { path, instance, .. } = cmd;
+    let p = path.trim_end_matches('/');
+    let p = match cmd.scope {
+        Scope::Project => p.rsplit_once('/').map(|(p, _)| p).unwrap_or(p),
+        Scope::Group => p,
+    };
+    let item = Item::new("/boards")
+        .subtitle(format!("Open the issue boards for {path}"))
+        .arg(format!("{}/groups/{p}/-/boards", instance.url));
+    Some(item)
+}
+
+fn list_item(cmd: &Command) -> Option<Item> {
+    let Command { path, instance, .. } = cmd;
+    let url = match cmd.scope {
+        Scope::Project => format!("{}/{path}/-/issues", instance.url),
+        Scope::Group => format!("{}/groups/{path}/-/issues", instance.url),
+    };
+    let item = Item::new("/list")
+        .subtitle(format!("Open the issue list for {path}"))
+        .arg(url);
+    Some(item)
+}
+
+/// The name of the built-in command that lists the user's own work.
+const MY_WORK: &str = ":me";
+
+fn my_work_item() -> Item {
+    Item::new(MY_WORK)
+        .subtitle("Search issues and merge requests that involve you")
+        .valid(false)
+        .autocomplete(format!("{MY_WORK} "))
+}
+
+/// Lists the open issues and merge requests on every configured instance that
+/// are waiting for the user's review, assigned to them, or authored by them.
+fn my_work(query: &str) -> Result<Vec<Item>> {
+    let now = chrono::Utc::now();
+    let query = match query::parse(query) {
+        Ok(query) => query,
+        Err(err) => return Ok(vec![query_error_item(&err)]),
+    };
+    if query.state != Some(State::Opened) {
+        let item = Item::new("Only open items are listed")
+            .subtitle("Search a command to find items in other states")
+            .valid(false);
+        return Ok(vec![item]);
+    }
+    let ttl = refresh_ttl(&query, CONFIG.ttl);
+
+    let mut instances: Vec<_> = CONFIG
+        .instances
+        .iter()
+        .filter(|i| i.token.is_some())
+        .collect();
+    if instances.is_empty() {
+        // This will error with a message saying which token is missing.
+        instances.push(&CONFIG.instances[0]);
+    }
+
+    // The same item can show up for multiple roles, it is only listed under
+    // the first one. Nothing waits for the data to be fetched, so every query
+    // is started straight away and its items are listed once it has been.
+    let mut seen = HashSet::new();
+    let mut items = Vec::new();
+    let mut status = Status::default();
+    for role in [Role::ReviewRequested, Role::Assigned, Role::Authored] {
+        for instance in &instances {
+            let name = format!("merge requests on {}", instance.url);
+            let merge_requests = gitlab::my_merge_requests(instance, role, ttl, &query);
+            for m in status.check_pending(&name, merge_requests) {
+                if let Some(score) = m.score(&query, now) {
+                    if seen.insert(m.url.clone()) {
+                        let prefix = format!("{role} · {}", m.project());
+                        items.push((score, m.into_item(now, Some(prefix))));
+                    }
+                }
+            }
+            let name = format!("issues on {}", instance.url);
+            let issues = gitlab::my_issues(instance, role, ttl, &query);
+            for i in status.check_pending(&name, issues) {
+                if let Some(score) = i.score(&query, now) {
+                    if seen.insert(i.url.clone()) {
+                        let prefix = format!("{role} · {}", i.project());
+                        items.push((score, i.into_item(now, Some(prefix))));
+                    }
+                }
+            }
+        }
+    }
+    // Sorting is stable so items that are equally relevant stay grouped by
+    // role.
+    items.sort_by_key(|(score, _)| Reverse(*score));
+    let items = items.into_iter().map(|(_, item)| item).collect();
+    Ok(status.prepend(items))
+}
+
+/// The name of the built-in command that searches every command.
+const SEARCH_ALL: &str = "*";
+
+fn search_all_item() -> Item {
+    Item::new(SEARCH_ALL)
+        .subtitle("Search issues and merge requests in every command")
+        .valid(false)
+        .autocomplete(format!("{SEARCH_ALL} "))
 }
 
-fn boards_item(project: &str) -> Item {
-    let p = project.trim_end_matches('/');
-    let p = p.rsplit_once('/').map(|(p, _)| p).unwrap_or(p);
-    Item::new("/boards")
-        .subtitle(format!("Open the issue boards for {project}"))
-        .arg(format!("https://gitlab.com/groups/{p}/-/boards"))
+/// Searches the cached data of every configured command, ranking the results
+/// together and labelling each with the command it came from. Nothing is
+/// fetched, commands without cached data or whose data can't be read are
+/// listed in front of the results instead.
+fn search_all(query: &str) -> Result<Vec<Item>> {
+    let now = chrono::Utc::now();
+    let query = match query::parse(query) {
+        Ok(query) => query,
+        Err(err) => return Ok(vec![query_error_item(&err)]),
+    };
+
+    let mut found = Vec::new();
+    let mut status = Status::default();
+    for cmd in &CONFIG.commands {
+        match search_cached(cmd, &query, now, &mut status) {
+            Ok(results) => found.extend(results),
+            Err(err) => status.fail(&cmd.name, err),
+        }
+    }
+    Ok(status.prepend(merge(found)))
 }
 
-fn list_item(project: &str) -> Item {
-    Item::new("/list")
-        .subtitle(format!("Open the issue list for {project}"))
-        .arg(format!("https://gitlab.com/{project}/-/issues"))
+/// Searches the cached data of a single command, returning the score, URL
+/// and item of each result.
+fn search_cached(
+    cmd: &Command,
+    query: &Query,
+    now: DateTime<chrono::Utc>,
+    status: &mut Status,
+) -> Result<Vec<(u32, String, Item)>> {
+    let state = query.state;
+    let label = |project: &str| match cmd.scope {
+        Scope::Project => cmd.name.clone(),
+        Scope::Group => format!("{} · {project}", cmd.name),
+    };
+    let results = match cmd.kind {
+        // Issues can't be merged.
+        Kind::Issues if state == Some(State::Merged) => Vec::new(),
+        Kind::Issues => {
+            let issues = gitlab::cached_issues(&cmd.instance, cmd.scope, &cmd.path, state, query)?;
+            rank(
+                status.check_cached(&cmd.name, issues),
+                |i| i.score(query, now),
+                Issue::ours_first,
+            )
+            .into_iter()
+            .map(|(score, i)| {
+                let prefix = label(i.project());
+                (score, i.url.clone(), i.into_item(now, Some(prefix)))
+            })
+            .collect()
+        }
+        Kind::MergeRequests => {
+            let merge_requests =
+                gitlab::cached_merge_requests(&cmd.instance, cmd.scope, &cmd.path, state, query)?;
+            rank(
+                status.check_cached(&cmd.name, merge_requests),
+                |m| m.score(query, now),
+                MergeRequest::ours_first,
+            )
+            .into_iter()
+            .map(|(score, m)| {
+                let prefix = label(m.project());
+                (score, m.url.clone(), m.into_item(now, Some(prefix)))
+            })
+            .collect()
+        }
+    };
+    Ok(results)
+}
+
+/// Merges the results of several commands, which can share a project, so
+/// only the first result with each URL is kept. Sorting is stable so results
+/// that are equally relevant stay in the order of the commands.
+fn merge<T>(results: Vec<(u32, String, T)>) -> Vec<T> {
+    let mut seen = HashSet::new();
+    let mut results: Vec<_> = results
+        .into_iter()
+        .filter(|(_, url, _)| seen.insert(url.clone()))
+        .collect();
+    results.sort_by_key(|(score, ..)| Reverse(*score));
+    results.into_iter().map(|(_, _, item)| item).collect()
+}
+
+/// Tracks the state of the cached data across several cache entries.
+#[derive(Default)]
+struct Status {
+    /// The oldest data that failed to refresh, if any.
+    stale: Option<(SystemTime, gitlab::Error)>,
+    /// The progress of any data that is still being fetched for the first
+    /// time.
+    loading: Option<cache::Progress>,
+    /// The commands that have no cached data.
+    missing: Vec<String>,
+    /// The data that couldn't be loaded, along with the error.
+    failed: Vec<(String, String)>,
+}
+
+impl Status {
+    /// Records the state of the loaded data, returning the items.
+    fn check<T>(&mut self, loaded: gitlab::Loaded<T>) -> Vec<T> {
+        if let Some(error) = loaded.error {
+            if self
+                .stale
+                .as_ref()
+                .is_none_or(|(m, _)| loaded.modified < *m)
+            {
+                self.stale = Some((loaded.modified, error));
+            }
+        }
+        self.loading = self.loading.or(loaded.progress);
+        loaded.data
+    }
+
+    /// Records the state of the cached data of a command, returning the
+    /// items.
+    fn check_cached<T>(&mut self, name: &str, loaded: Option<gitlab::Loaded<T>>) -> Vec<T> {
+        match loaded {
+            Some(loaded) => self.check(loaded),
+            None => {
+                self.missing.push(name.to_owned());
+                Vec::new()
+            }
+        }
+    }
+
+    /// Records the state of data that is loaded without waiting for it to be
+    /// fetched, which is `None` until the first page has been. Returns the
+    /// items.
+    fn check_pending<T>(
+        &mut self,
+        name: &str,
+        loaded: Result<Option<gitlab::Loaded<T>>>,
+    ) -> Vec<T> {
+        match loaded {
+            Ok(Some(loaded)) => self.check(loaded),
+            Ok(None) => {
+                self.loading = self.loading.or(Some(cache::Progress::default()));
+                Vec::new()
+            }
+            Err(err) => {
+                self.fail(name, err);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Records that the named data couldn't be loaded. The same error is only
+    /// recorded once for each name.
+    fn fail(&mut self, name: &str, err: anyhow::Error) {
+        let failure = (name.to_owned(), format!("{err:#}"));
+        if !self.failed.contains(&failure) {
+            self.failed.push(failure);
+        }
+    }
+
+    /// Adds the status items in front of the given items.
+    fn prepend(self, mut items: Vec<Item>) -> Vec<Item> {
+        if let Some(item) = loading_item(self.loading) {
+            items.insert(0, item);
+        }
+        if !self.missing.is_empty() {
+            let item = Item::new(format!("Nothing cached for {}", self.missing.join(", ")))
+                .subtitle("Search the command on its own to fetch its data")
+                .valid(false);
+            items.insert(0, item);
+        }
+        for (name, err) in self.failed.into_iter().rev() {
+            let item = Item::new(format!("Failed to search {name}"))
+                .subtitle(err)
+                .valid(false);
+            items.insert(0, item);
+        }
+        let stale = self.stale;
+        if let Some(item) = stale.and_then(|(modified, error)| status_item(modified, Some(&error)))
+        {
+            items.insert(0, item);
+        }
+        items
+    }
+}
+
+/// The name of the built-in command that manages the cache.
+const CACHE: &str = ":cache";
+
+fn cache_item() -> Item {
+    Item::new(CACHE)
+        .subtitle("Show and clear cached data")
+        .valid(false)
+        .autocomplete(format!("{CACHE} "))
+}
+
+/// The argument that clears cached data instead of running a query, passed
+/// by the action that runs when a `:cache` item is selected with ⌘.
+const CLEAR: &str = "--clear";
+
+/// Lists the cache entries. Listing them never changes the cache, the items
+/// can be selected with ⌘ to clear them.
+fn cache(query: &str) -> Result<Vec<Item>> {
+    let mut items = Vec::new();
+    let entries = cache::entries::<gitlab::Error>()?;
+    let total: u64 = entries.iter().map(|e| e.size).sum();
+    items.push(
+        Item::new("Clear all")
+            .subtitle(format!(
+                "{} {} using {}, press ⌘↩ to clear",
+                entries.len(),
+                if entries.len() == 1 {
+                    "entry"
+                } else {
+                    "entries"
+                },
+                human::format_size(total)
+            ))
+            .valid(false)
+            .modifier(clear_modifier("Clear all cached data", "all")),
+    );
+    for entry in entries {
+        let key = entry.key.as_deref().unwrap_or("Unknown");
+        if !key.contains(query.trim()) {
+            continue;
+        }
+        let mut subtitle = human::format_size(entry.size);
+        match entry.modified.and_then(|m| m.elapsed().ok()) {
+            Some(age) => subtitle.push_str(&format!(", updated {}", human::format_ago(age))),
+            None => subtitle.push_str(", no data"),
+        }
+        if let Some(holder) = &entry.updating {
+            let age = holder.started.elapsed().unwrap_or_default();
+            subtitle.push_str(&format!(
+                ", update started {} by process {}",
+                human::format_ago(age),
+                holder.pid
+            ));
+        }
+        if let Some(error) = &entry.error {
+            subtitle.push_str(&format!(", last error: {error}"));
+        }
+        items.push(
+            Item::new(key)
+                .subtitle(subtitle)
+                .valid(false)
+                .modifier(clear_modifier("Clear this cached data", &entry.id)),
+        );
+    }
+    Ok(items)
+}
+
+fn clear_modifier(subtitle: &str, id: &str) -> Modifier {
+    Modifier::new(Key::Command)
+        .subtitle(subtitle)
+        .arg(id)
+        .valid(true)
+}
+
+/// The ⌘ modifier for items other than the ones listed by `:cache`. The
+/// workflow connects ⌘ to clearing the cache for every item, so without it
+/// ⌘↩ would try to clear an entry with the item's argument as the id.
+fn no_clear_modifier() -> Modifier {
+    Modifier::new(Key::Command)
+        .subtitle("Only cached data listed by :cache can be cleared")
+        .valid(false)
+}
+
+/// Clears the cache entry with the given id, or every entry for `all`.
+fn clear(id: &str) -> Result<()> {
+    match id {
+        "all" => cache::clear_all(),
+        id => cache::clear(id),
+    }
 }
 
 fn run() -> Result<()> {
+    if env::args().nth(1).as_deref() == Some(CLEAR) {
+        let id = env::args().nth(2).unwrap_or_default();
+        return clear(id.trim());
+    }
+
     let arg = env::args()
         .nth(1)
         .as_deref()
         .map(str::trim)
         .map(str::to_lowercase);
 
+    // Failing to collect garbage shouldn't stop anything from being listed.
+    if let Err(err) = cache::gc(gitlab::referenced()) {
+        eprintln!("failed to collect garbage: {err:#}");
+    }
+
+    let mut is_cache = false;
     let items = match arg {
         // If no argument is given then just list the available commands.
-        None => CONFIG.commands.iter().map(Command::to_item).collect(),
+        None => CONFIG
+            .commands
+            .iter()
+            .map(Command::to_item)
+            .chain([search_all_item(), my_work_item(), cache_item()])
+            .collect(),
 
         // Otherwise process the argument.
         Some(arg) => {
@@ -229,17 +1073,40 @@ fn run() -> Result<()> {
                 // There is a command that matches this query so execute it.
                 Some(cmd) => cmd.exec(query)?,
 
+                // The built-in command was entered.
+                None if cmd == SEARCH_ALL => search_all(query)?,
+                None if cmd == MY_WORK => my_work(query)?,
+                None if cmd == CACHE => {
+                    is_cache = true;
+                    cache(query)?
+                }
+
                 // No command matches the query exactly, output the commands
                 // that start with the half-entered command.
                 None => CONFIG
                     .commands
                     .iter()
-                    .filter_map(|c| c.name.starts_with(cmd).then(|| c.to_item()))
+                    .filter(|c| c.name.starts_with(cmd))
+                    .map(Command::to_item)
+                    .chain(SEARCH_ALL.starts_with(cmd).then(search_all_item))
+                    .chain(MY_WORK.starts_with(cmd).then(my_work_item))
+                    .chain(CACHE.starts_with(cmd).then(cache_item))
                     .collect(),
             }
         }
     };
 
+    // Options with invalid values are ignored, but the user should know.
+    let items = CONFIG
+        .invalid
+        .iter()
+        .map(invalid_option_item)
+        .chain(items)
+        .map(|item| match is_cache {
+            true => item,
+            false => item.modifier(no_clear_modifier()),
+        });
+
     powerpack::Output::new()
         .items(items)
         .rerun(Duration::from_secs(1))
@@ -248,14 +1115,300 @@ fn run() -> Result<()> {
     Ok(())
 }
 
+/// Returns an item describing an option that was ignored because its value
+/// is invalid.
+fn invalid_option_item(message: &String) -> Item {
+    Item::new(format!("Invalid option: {message}"))
+        .subtitle("Using the default instead, check the workflow configuration")
+        .valid(false)
+}
+
+/// Returns an item describing a GitLab error with a hint on how to fix it.
+fn gitlab_error_item(err: &gitlab::Error) -> Item {
+    let item = Item::new(format!("Error: {err}"));
+    match err {
+        gitlab::Error::NotFound { .. } => item.subtitle(
+            "Check the path in the workflow configuration \
+             and that your token has access to it",
+        ),
+        gitlab::Error::Unauthorized { url } => item
+            .subtitle("Press ↩ to create a new personal access token")
+            .arg(token_url(url)),
+        gitlab::Error::InsufficientScope { url } => item
+            .subtitle("Press ↩ to create a new token with the `read_api` scope")
+            .arg(token_url(url)),
+        gitlab::Error::RateLimited => item.subtitle("Wait a minute and try again"),
+        gitlab::Error::Server { .. } => {
+            item.subtitle("GitLab might be having problems, try again later")
+        }
+        gitlab::Error::Network { .. } => {
+            item.subtitle("Check your connection and the proxy in the workflow configuration")
+        }
+        gitlab::Error::Other { .. } => item.subtitle(
+            "The workflow errored! \
+             You might want to try debugging it or checking the logs.",
+        ),
+    }
+}
+
+fn token_url(url: &str) -> String {
+    format!(
+        "{url}/-/profile/personal_access_tokens\
+         ?name=gitlab.alfredworkflow&scopes=read_api;Personal access tokens"
+    )
+}
+
 fn main() -> Result<()> {
     if let Err(err) = run() {
         eprintln!("{err:#}");
-        let item = Item::new(format!("Error: {err}")).subtitle(
-            "The workflow errored! \
-             You might want to try debugging it or checking the logs.",
-        );
+        let item = match err.downcast_ref() {
+            Some(err) => gitlab_error_item(err),
+            None => Item::new(format!("Error: {err}")).subtitle(
+                "The workflow errored! \
+                 You might want to try debugging it or checking the logs.",
+            ),
+        };
         powerpack::output(iter::once(item))?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json as json;
+
+    use super::*;
+
+    fn instance(name: &str, url: &str) -> Instance {
+        Instance {
+            name: name.to_owned(),
+            url: url.to_owned(),
+            token: None,
+        }
+    }
+
+    fn command(kind: Kind, scope: Scope, path: &str, instance: &Instance) -> Command {
+        Command {
+            kind,
+            scope,
+            name: "test".to_owned(),
+            path: path.to_owned(),
+            instance: instance.clone(),
+            ttl: None,
+        }
+    }
+
+    /// Resolves the query, returning the instance name, path, kind and IID.
+    fn resolve(
+        cmd: &Command,
+        instances: &[Instance],
+        query: &str,
+    ) -> Option<(String, String, Kind, u64)> {
+        let reference = query::parse_reference(query)?;
+        let target = cmd.resolve(reference, instances)?;
+        Some((
+            target.instance.name.clone(),
+            target.path,
+            target.kind,
+            target.iid,
+        ))
+    }
+
+    fn target(
+        instance: &str,
+        path: &str,
+        kind: Kind,
+        iid: u64,
+    ) -> Option<(String, String, Kind, u64)> {
+        Some((instance.to_owned(), path.to_owned(), kind, iid))
+    }
+
+    #[test]
+    fn resolve_iid_in_project() {
+        let instances = [instance("default", "https://gitlab.com")];
+        let cmd = command(Kind::Issues, Scope::Project, "group/project", &instances[0]);
+        assert_eq!(
+            resolve(&cmd, &instances, "12"),
+            target("default", "group/project", Kind::Issues, 12)
+        );
+        assert_eq!(
+            resolve(&cmd, &instances, "!34"),
+            target("default", "group/project", Kind::MergeRequests, 34)
+        );
+    }
+
+    #[test]
+    fn resolve_iid_in_group() {
+        let instances = [instance("default", "https://gitlab.com")];
+        let cmd = command(Kind::Issues, Scope::Group, "group", &instances[0]);
+        assert_eq!(resolve(&cmd, &instances, "12"), None);
+        assert_eq!(
+            resolve(&cmd, &instances, "group/other#12"),
+            target("default", "group/other", Kind::Issues, 12)
+        );
+    }
+
+    #[test]
+    fn resolve_reference_on_command_instance() {
+        let instances = [
+            instance("default", "https://gitlab.com"),
+            instance("work", "https://git.example.com"),
+        ];
+        let cmd = command(Kind::MergeRequests, Scope::Project, "a/b", &instances[1]);
+        assert_eq!(
+            resolve(&cmd, &instances, "c/d!7"),
+            target("work", "c/d", Kind::MergeRequests, 7)
+        );
+    }
+
+    #[test]
+    fn resolve_url_on_any_instance() {
+        let instances = [
+            instance("default", "https://gitlab.com"),
+            instance("work", "https://GIT.example.com"),
+        ];
+        let cmd = command(Kind::MergeRequests, Scope::Project, "a/b", &instances[0]);
+        assert_eq!(
+            resolve(&cmd, &instances, "https://git.example.com/c/d/-/issues/12"),
+            target("work", "c/d", Kind::Issues, 12)
+        );
+        assert_eq!(
+            resolve(
+                &cmd,
+                &instances,
+                "https://gitlab.com/a/b/-/merge_requests/3/diffs"
+            ),
+            target("default", "a/b", Kind::MergeRequests, 3)
+        );
+    }
+
+    #[test]
+    fn resolve_url_on_instance_with_sub_path() {
+        let instances = [
+            instance("default", "https://example.com"),
+            instance("gitlab", "https://example.com/gitlab"),
+        ];
+        let cmd = command(Kind::Issues, Scope::Project, "a/b", &instances[0]);
+        assert_eq!(
+            resolve(
+                &cmd,
+                &instances[1..],
+                "https://example.com/gitlab/c/d/-/issues/12"
+            ),
+            target("gitlab", "c/d", Kind::Issues, 12)
+        );
+    }
+
+    #[test]
+    fn resolve_url_on_unknown_instance() {
+        let instances = [instance("default", "https://git.example.com")];
+        let cmd = command(Kind::Issues, Scope::Project, "a/b", &instances[0]);
+        assert_eq!(
+            resolve(&cmd, &instances, "https://gitlab.com/a/b/-/issues/12"),
+            None
+        );
+        // The instance URL must be followed by a path separator.
+        assert_eq!(
+            resolve(
+                &cmd,
+                &instances,
+                "https://git.example.com.evil/a/b/-/issues/12"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn merge_keeps_first_of_each_url() {
+        let results = vec![
+            (1, "a".to_owned(), "a1"),
+            (3, "b".to_owned(), "b1"),
+            (3, "a".to_owned(), "a2"),
+            (3, "c".to_owned(), "c1"),
+        ];
+        assert_eq!(merge(results), ["b1", "c1", "a1"]);
+    }
+
+    #[test]
+    fn status_check_cached() {
+        let mut status = Status::default();
+        let loaded = cache::Loaded {
+            data: vec![1, 2],
+            modified: SystemTime::now(),
+            error: None,
+            progress: None,
+        };
+        assert_eq!(status.check_cached("a", Some(loaded)), [1, 2]);
+        assert!(status.check_cached::<u32>("b", None).is_empty());
+        assert_eq!(status.missing, ["b"]);
+    }
+
+    #[test]
+    fn status_item_offline_only_for_network_errors() {
+        let title = |error| {
+            let item = status_item(SystemTime::now(), Some(&error)).unwrap();
+            json::to_value(item).unwrap()["title"].clone()
+        };
+        let message = "Could not resolve host: gitlab.com".to_owned();
+        assert_eq!(
+            title(gitlab::Error::Network { message }),
+            "Offline — showing data from a few seconds ago"
+        );
+        let message = "expected value at line 1 column 1".to_owned();
+        assert_eq!(
+            title(gitlab::Error::Other { message }),
+            "Refresh failed — showing data from a few seconds ago"
+        );
+    }
+
+    #[test]
+    fn status_check_pending() {
+        let mut status = Status::default();
+        let loaded = cache::Loaded {
+            data: vec![1, 2],
+            modified: SystemTime::now(),
+            error: None,
+            progress: None,
+        };
+        assert_eq!(status.check_pending("a", Ok(Some(loaded))), [1, 2]);
+        assert!(status.loading.is_none());
+        assert!(status.check_pending::<u32>("b", Ok(None)).is_empty());
+        assert!(status.loading.is_some());
+        for _ in 0..2 {
+            let err = anyhow::anyhow!("no token");
+            assert!(status.check_pending::<u32>("c", Err(err)).is_empty());
+        }
+        assert_eq!(status.failed, [("c".to_owned(), "no token".to_owned())]);
+    }
+
+    #[test]
+    fn unsupported_filter_item_ignores_negated_terms() {
+        let query = query::parse("is:draft").unwrap();
+        assert!(unsupported_filter_item(Kind::Issues, &query).is_some());
+        assert!(unsupported_filter_item(Kind::MergeRequests, &query).is_none());
+        let query = query::parse("-is:draft -is:confidential").unwrap();
+        assert!(unsupported_filter_item(Kind::Issues, &query).is_none());
+        assert!(unsupported_filter_item(Kind::MergeRequests, &query).is_none());
+    }
+
+    #[test]
+    fn status_prepend_failed_and_missing() {
+        let mut status = Status::default();
+        status.fail("a", anyhow::anyhow!("no token").context("failed to load"));
+        status.check_cached::<u32>("b", None);
+        status.check_cached::<u32>("c", None);
+        let items = status.prepend(vec![Item::new("result")]);
+        assert_eq!(
+            items,
+            [
+                Item::new("Failed to search a")
+                    .subtitle("failed to load: no token")
+                    .valid(false),
+                Item::new("Nothing cached for b, c")
+                    .subtitle("Search the command on its own to fetch its data")
+                    .valid(false),
+                Item::new("result"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,682 @@
+//! Parses search queries into the terms that items must match.
+//!
+//! A query is made up of whitespace separated terms:
+//!
+//! - `word` fuzzily matches the title or description.
+//! - `"some phrase"` matches titles or descriptions that contain the phrase.
+//! - `~label`, `@user` and `%milestone` match labels, people and milestones.
+//! - `#123` and `!45` match the item with that IID.
+//! - `is:draft`, `is:confidential`, `created:<7d` and `updated:>2w` match
+//!   drafts, confidential issues and how long ago items were created or
+//!   updated.
+//! - `is:open`, `is:closed`, `is:merged`, `is:locked` and `is:all` choose the
+//!   state to search, and `cache:refresh` refreshes the cached data.
+//!
+//! Any term except the state and refresh qualifiers can be prefixed with `-`
+//! to exclude the items that match it.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::human;
+
+use crate::config::Kind;
+use crate::State;
+
+/// A parsed search query.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Query {
+    /// The state to search, `None` searches every state.
+    pub state: Option<State>,
+    /// Whether to refresh the cached data immediately.
+    pub refresh: bool,
+    /// The terms that every item must match.
+    pub terms: Vec<Term>,
+}
+
+/// A single term of a query.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Term {
+    /// Whether items that match the filter should be excluded instead.
+    pub negated: bool,
+    pub filter: Filter,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// A word matched fuzzily against the title or description.
+    Word(String),
+    /// A phrase that the title or description must contain.
+    Phrase(String),
+    /// Part of the title of a label.
+    Label(String),
+    /// Part of the name or username of the author, an assignee or a reviewer.
+    User(String),
+    /// Part of the title of the milestone.
+    Milestone(String),
+    /// The IID of the item within its project.
+    Iid(u64),
+    /// Merge requests that are marked as drafts.
+    Draft,
+    /// Issues that are confidential.
+    Confidential,
+    /// How long ago the item was created.
+    Created(Age),
+    /// How long ago the item was last updated.
+    Updated(Age),
+}
+
+/// A bound on how long ago something happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Age {
+    /// Less than the duration ago, written as `<7d`.
+    Within(Duration),
+    /// More than the duration ago, written as `>7d`.
+    Before(Duration),
+}
+
+/// An issue or merge request that the query refers to directly, by its IID,
+/// a full reference, or a web URL.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Reference {
+    /// The web URL of the project, if the item was given as a URL.
+    pub project_url: Option<String>,
+    /// The full path of the project, if the item was given as a reference.
+    pub path: Option<String>,
+    /// The kind of item, if it was given with a `#` or `!`.
+    pub kind: Option<Kind>,
+    pub iid: u64,
+}
+
+impl Reference {
+    /// Whether the reference names its project. Those are usually pasted,
+    /// whereas an IID on its own might be the start of a longer one that is
+    /// still being typed.
+    pub fn is_complete(&self) -> bool {
+        self.project_url.is_some() || self.path.is_some()
+    }
+}
+
+/// An error parsing a query, shown to the user along with a hint on how to
+/// fix it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Error {
+    message: String,
+    hint: &'static str,
+}
+
+impl Age {
+    /// Whether the given age is within the bound.
+    pub fn matches(&self, age: Duration) -> bool {
+        match *self {
+            Self::Within(d) => age < d,
+            Self::Before(d) => age > d,
+        }
+    }
+}
+
+impl Error {
+    fn new(message: impl Into<String>, hint: &'static str) -> Self {
+        Self {
+            message: message.into(),
+            hint,
+        }
+    }
+
+    /// Returns a hint on how to fix the query.
+    pub fn hint(&self) -> &str {
+        self.hint
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A token of the query with the quotes removed.
+struct Token {
+    text: String,
+    /// The position in the text where the first quote was, if any.
+    quoted: Option<usize>,
+}
+
+/// Parses a search query. Terms that haven't been finished yet, like a `~`
+/// without a label, are ignored so that the results don't disappear while
+/// typing.
+pub fn parse(query: &str) -> Result<Query, Error> {
+    let mut q = Query {
+        state: Some(State::Opened),
+        refresh: false,
+        terms: Vec::new(),
+    };
+    for token in tokenize(query)? {
+        parse_token(&mut q, token)?;
+    }
+    Ok(q)
+}
+
+/// Parses a query that refers to a single item, like `123`, `#123`,
+/// `group/project!45`, or a web URL of an issue or merge request.
+pub fn parse_reference(query: &str) -> Option<Reference> {
+    let query = query.trim();
+    if query.contains(char::is_whitespace) {
+        return None;
+    }
+    if query.starts_with("https://") || query.starts_with("http://") {
+        return parse_url(query);
+    }
+    let (path, kind, iid) = match query.rfind(['#', '!']) {
+        Some(i) => {
+            let kind = match &query[i..i + 1] {
+                "#" => Kind::Issues,
+                _ => Kind::MergeRequests,
+            };
+            let path = &query[..i];
+            let path = (!path.is_empty()).then(|| path.to_owned());
+            (path, Some(kind), &query[i + 1..])
+        }
+        None => (None, None, query),
+    };
+    if path.as_deref().is_some_and(|p| !p.contains('/')) {
+        return None;
+    }
+    Some(Reference {
+        project_url: None,
+        path,
+        kind,
+        iid: parse_iid(iid)?,
+    })
+}
+
+/// Parses a URL like `https://gitlab.com/group/project/-/issues/123`.
+fn parse_url(url: &str) -> Option<Reference> {
+    let (project_url, rest) = url.split_once("/-/")?;
+    let (kind, rest) = rest.split_once('/')?;
+    let kind = match kind {
+        "issues" | "work_items" => Kind::Issues,
+        "merge_requests" => Kind::MergeRequests,
+        _ => return None,
+    };
+    // The IID can be followed by a tab of the page, a query, or a fragment.
+    let iid = rest.split(['/', '?', '#']).next()?;
+    Some(Reference {
+        project_url: Some(project_url.to_owned()),
+        path: None,
+        kind: Some(kind),
+        iid: parse_iid(iid)?,
+    })
+}
+
+fn parse_iid(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut token: Option<Token> = None;
+    let mut in_quotes = false;
+    for c in query.chars() {
+        match c {
+            '"' => {
+                let t = token.get_or_insert_with(|| Token {
+                    text: String::new(),
+                    quoted: None,
+                });
+                t.quoted.get_or_insert(t.text.len());
+                in_quotes = !in_quotes;
+            }
+            c if c.is_whitespace() && !in_quotes => tokens.extend(token.take()),
+            c => token
+                .get_or_insert_with(|| Token {
+                    text: String::new(),
+                    quoted: None,
+                })
+                .text
+                .push(c),
+        }
+    }
+    if in_quotes {
+        return Err(Error::new(
+            "Missing a closing quote",
+            "Add a `\"` to the end of the phrase",
+        ));
+    }
+    tokens.extend(token);
+    Ok(tokens)
+}
+
+fn parse_token(q: &mut Query, token: Token) -> Result<(), Error> {
+    let Token { mut text, quoted } = token;
+    // Only characters before the first quote can be special.
+    let plain = quoted.unwrap_or(text.len());
+
+    let negated = plain > 0 && text.starts_with('-');
+    let plain = if negated {
+        text.remove(0);
+        plain - 1
+    } else {
+        plain
+    };
+
+    let sigil = text.chars().next().filter(|_| plain > 0);
+    let rest = || text[1..].to_owned();
+    let filter = match sigil {
+        Some('~') => Filter::Label(rest()),
+        Some('@') => Filter::User(rest()),
+        Some('%') => Filter::Milestone(rest()),
+        Some('#' | '!') => {
+            let iid = rest();
+            if iid.is_empty() {
+                return Ok(());
+            }
+            match iid.parse() {
+                Ok(iid) => Filter::Iid(iid),
+                Err(_) => {
+                    return Err(Error::new(
+                        format!("`{text}` is not a valid IID"),
+                        "Use a number like #123 or !45",
+                    ))
+                }
+            }
+        }
+        _ => match text[..plain].split_once(':') {
+            Some((key @ ("is" | "created" | "updated" | "cache"), _)) => {
+                let value = &text[key.len() + 1..];
+                match parse_qualifier(q, key, value, negated)? {
+                    Some(filter) => filter,
+                    None => return Ok(()),
+                }
+            }
+            _ if quoted.is_some() => Filter::Phrase(text),
+            _ => Filter::Word(text),
+        },
+    };
+    let is_empty = match &filter {
+        Filter::Word(s)
+        | Filter::Phrase(s)
+        | Filter::Label(s)
+        | Filter::User(s)
+        | Filter::Milestone(s) => s.is_empty(),
+        _ => false,
+    };
+    if !is_empty {
+        q.terms.push(Term { negated, filter });
+    }
+    Ok(())
+}
+
+/// Parses a `key:value` qualifier. Returns `None` if the qualifier doesn't
+/// add a filter, either because it changes an option of the query or because
+/// it hasn't been finished yet.
+fn parse_qualifier(
+    q: &mut Query,
+    key: &str,
+    value: &str,
+    negated: bool,
+) -> Result<Option<Filter>, Error> {
+    let option = match (key, value) {
+        ("is", "open" | "opened") => Some(Some(State::Opened)),
+        ("is", "closed") => Some(Some(State::Closed)),
+        ("is", "merged") => Some(Some(State::Merged)),
+        ("is", "locked") => Some(Some(State::Locked)),
+        ("is", "all") => Some(None),
+        ("cache", "refresh") => None,
+        ("is", "draft") => return Ok(Some(Filter::Draft)),
+        ("is", "confidential") => return Ok(Some(Filter::Confidential)),
+        (_, "") | ("created" | "updated", "<" | ">") => return Ok(None),
+        ("is", _) => {
+            return Err(Error::new(
+                format!("Unknown qualifier `is:{value}`"),
+                "Use is:open, is:closed, is:merged, is:locked, is:all, \
+                 is:draft or is:confidential",
+            ))
+        }
+        ("cache", _) => {
+            return Err(Error::new(
+                format!("Unknown qualifier `cache:{value}`"),
+                "Use cache:refresh to refresh the cached data",
+            ))
+        }
+        _ => {
+            return match parse_age(value) {
+                Some(age) if key == "created" => Ok(Some(Filter::Created(age))),
+                Some(age) => Ok(Some(Filter::Updated(age))),
+                None => Err(Error::new(
+                    format!("`{key}:{value}` is not a valid age"),
+                    "Use < or > followed by a number and h, d, w or y, like created:<7d",
+                )),
+            }
+        }
+    };
+    if negated {
+        return Err(Error::new(
+            format!("`{key}:{value}` can't be negated"),
+            "Remove the `-` in front of it",
+        ));
+    }
+    match option {
+        Some(state) => q.state = state,
+        None => q.refresh = true,
+    }
+    Ok(None)
+}
+
+/// Parses an age like `<7d` or `>2w`, the comparison defaults to `<`.
+fn parse_age(s: &str) -> Option<Age> {
+    let (within, s) = match s.strip_prefix('>') {
+        Some(s) => (false, s),
+        None => (true, s.strip_prefix('<').unwrap_or(s)),
+    };
+    // Only the units in the hint are accepted, `m` could be taken for months.
+    if !s.ends_with(['h', 'd', 'w', 'y']) {
+        return None;
+    }
+    let d = human::parse_duration(s)?;
+    Some(if within {
+        Age::Within(d)
+    } else {
+        Age::Before(d)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn term(filter: Filter) -> Term {
+        Term {
+            negated: false,
+            filter,
+        }
+    }
+
+    fn not(filter: Filter) -> Term {
+        Term {
+            negated: true,
+            filter,
+        }
+    }
+
+    fn terms(query: &str) -> Vec<Term> {
+        parse(query).unwrap().terms
+    }
+
+    fn word(s: &str) -> Filter {
+        Filter::Word(s.to_owned())
+    }
+
+    #[test]
+    fn parse_empty() {
+        let q = parse("").unwrap();
+        assert_eq!(q.state, Some(State::Opened));
+        assert!(!q.refresh);
+        assert!(q.terms.is_empty());
+        assert!(terms("   ").is_empty());
+    }
+
+    #[test]
+    fn parse_words() {
+        assert_eq!(
+            terms("fix  the\tpipeline"),
+            [term(word("fix")), term(word("the")), term(word("pipeline"))]
+        );
+    }
+
+    #[test]
+    fn parse_sigils() {
+        assert_eq!(
+            terms("~bug @ross %v1.0"),
+            [
+                term(Filter::Label("bug".into())),
+                term(Filter::User("ross".into())),
+                term(Filter::Milestone("v1.0".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_negation() {
+        assert_eq!(
+            terms("-~wontfix -@bot -flaky"),
+            [
+                not(Filter::Label("wontfix".into())),
+                not(Filter::User("bot".into())),
+                not(word("flaky")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_hyphen_in_word() {
+        assert_eq!(terms("follow-up"), [term(word("follow-up"))]);
+        assert_eq!(terms("--force"), [not(word("-force"))]);
+    }
+
+    #[test]
+    fn parse_iid() {
+        assert_eq!(
+            terms("#123 !45 -#6"),
+            [
+                term(Filter::Iid(123)),
+                term(Filter::Iid(45)),
+                not(Filter::Iid(6)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_invalid_iid() {
+        let err = parse("#12a").unwrap_err();
+        assert_eq!(err.to_string(), "`#12a` is not a valid IID");
+        assert!(parse("!abc").is_err());
+    }
+
+    #[test]
+    fn parse_phrase() {
+        assert_eq!(
+            terms(r#""deploy fails" now"#),
+            [
+                term(Filter::Phrase("deploy fails".into())),
+                term(word("now"))
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_quoted_sigils() {
+        assert_eq!(
+            terms(r#"~"needs review" -"won't fix" "~literal""#),
+            [
+                term(Filter::Label("needs review".into())),
+                not(Filter::Phrase("won't fix".into())),
+                term(Filter::Phrase("~literal".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_quoted_qualifier() {
+        assert_eq!(
+            terms(r#""is:draft""#),
+            [term(Filter::Phrase("is:draft".into()))]
+        );
+    }
+
+    #[test]
+    fn parse_unterminated_quote() {
+        let err = parse(r#"~bug "deploy fa"#).unwrap_err();
+        assert_eq!(err.to_string(), "Missing a closing quote");
+    }
+
+    #[test]
+    fn parse_state() {
+        assert_eq!(parse("is:closed").unwrap().state, Some(State::Closed));
+        assert_eq!(parse("is:merged").unwrap().state, Some(State::Merged));
+        assert_eq!(parse("is:locked").unwrap().state, Some(State::Locked));
+        assert_eq!(parse("is:opened").unwrap().state, Some(State::Opened));
+        assert_eq!(parse("is:all").unwrap().state, None);
+        // The last state wins.
+        assert_eq!(parse("is:all is:open").unwrap().state, Some(State::Opened));
+        assert!(terms("is:closed").is_empty());
+    }
+
+    #[test]
+    fn parse_refresh() {
+        let q = parse("deploy cache:refresh").unwrap();
+        assert!(q.refresh);
+        assert_eq!(q.terms, [term(word("deploy"))]);
+    }
+
+    #[test]
+    fn parse_is_filters() {
+        assert_eq!(
+            terms("is:draft -is:confidential"),
+            [term(Filter::Draft), not(Filter::Confidential)]
+        );
+    }
+
+    #[test]
+    fn parse_unknown_qualifier() {
+        let err = parse("is:nope").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown qualifier `is:nope`");
+        assert!(parse("cache:clear").is_err());
+    }
+
+    #[test]
+    fn parse_negated_option() {
+        assert!(parse("-is:closed").is_err());
+        assert!(parse("-cache:refresh").is_err());
+    }
+
+    #[test]
+    fn parse_other_colons_are_words() {
+        assert_eq!(
+            terms("fix: ci:lint"),
+            [term(word("fix:")), term(word("ci:lint"))]
+        );
+    }
+
+    #[test]
+    fn parse_ages() {
+        assert_eq!(
+            terms("created:<7d updated:>2w -created:>1y updated:12h"),
+            [
+                term(Filter::Created(Age::Within(DAY * 7))),
+                term(Filter::Updated(Age::Before(DAY * 14))),
+                not(Filter::Created(Age::Before(DAY * 365))),
+                term(Filter::Updated(Age::Within(DAY / 2))),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_invalid_age() {
+        let err = parse("created:<7x").unwrap_err();
+        assert_eq!(err.to_string(), "`created:<7x` is not a valid age");
+        assert!(parse("updated:>d").is_err());
+        assert!(parse("updated:<7").is_err());
+        assert!(parse("created:<3m").is_err());
+        assert!(parse("created:<30s").is_err());
+        assert!(parse("updated:<99999999999999999y").is_err());
+    }
+
+    #[test]
+    fn parse_unfinished_terms() {
+        assert!(terms("~ @ % # ! - -~ is: created: updated:<").is_empty());
+    }
+
+    fn reference(path: Option<&str>, kind: Option<Kind>, iid: u64) -> Option<Reference> {
+        Some(Reference {
+            project_url: None,
+            path: path.map(Into::into),
+            kind,
+            iid,
+        })
+    }
+
+    #[test]
+    fn parse_reference_iid() {
+        assert_eq!(parse_reference(" 1234 "), reference(None, None, 1234));
+        assert_eq!(
+            parse_reference("#12"),
+            reference(None, Some(Kind::Issues), 12)
+        );
+        assert_eq!(
+            parse_reference("!34"),
+            reference(None, Some(Kind::MergeRequests), 34)
+        );
+    }
+
+    #[test]
+    fn parse_reference_full() {
+        assert_eq!(
+            parse_reference("group/project#12"),
+            reference(Some("group/project"), Some(Kind::Issues), 12)
+        );
+        assert_eq!(
+            parse_reference("group/sub/project!34"),
+            reference(Some("group/sub/project"), Some(Kind::MergeRequests), 34)
+        );
+    }
+
+    #[test]
+    fn parse_reference_url() {
+        let expected = |kind, iid| {
+            Some(Reference {
+                project_url: Some("https://gitlab.com/group/project".into()),
+                path: None,
+                kind: Some(kind),
+                iid,
+            })
+        };
+        assert_eq!(
+            parse_reference("https://gitlab.com/group/project/-/issues/12"),
+            expected(Kind::Issues, 12)
+        );
+        assert_eq!(
+            parse_reference("https://gitlab.com/group/project/-/merge_requests/34/diffs"),
+            expected(Kind::MergeRequests, 34)
+        );
+        assert_eq!(
+            parse_reference("https://gitlab.com/group/project/-/issues/12#note_1"),
+            expected(Kind::Issues, 12)
+        );
+        assert_eq!(
+            parse_reference("https://gitlab.com/group/project/-/work_items/12?x=y"),
+            expected(Kind::Issues, 12)
+        );
+    }
+
+    #[test]
+    fn parse_reference_not_a_reference() {
+        assert_eq!(parse_reference(""), None);
+        assert_eq!(parse_reference("deploy"), None);
+        assert_eq!(parse_reference("12 deploy"), None);
+        assert_eq!(parse_reference("#12a"), None);
+        assert_eq!(parse_reference("+12"), None);
+        assert_eq!(parse_reference("c#12"), None);
+        assert_eq!(parse_reference("project#"), None);
+        assert_eq!(parse_reference("https://gitlab.com/group/project"), None);
+        assert_eq!(
+            parse_reference("https://gitlab.com/group/project/-/boards/1"),
+            None
+        );
+    }
+
+    #[test]
+    fn age_matches() {
+        assert!(Age::Within(DAY).matches(DAY / 2));
+        assert!(!Age::Within(DAY).matches(DAY * 2));
+        assert!(Age::Before(DAY).matches(DAY * 2));
+        assert!(!Age::Before(DAY).matches(DAY / 2));
+    }
+}
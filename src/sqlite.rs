@@ -0,0 +1,216 @@
+//! An optional backend that stores the cached issues and merge requests in a
+//! SQLite database with a full-text index, so that they can be searched
+//! without loading every item on each keystroke.
+
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use serde::de::DeserializeOwned;
+use serde_json as json;
+
+use crate::cache;
+use crate::query::{Filter, Query};
+
+/// The version of the database schema, the tables are recreated whenever it
+/// changes.
+const SCHEMA_VERSION: i64 = 2;
+
+const SCHEMA: &str = r#"
+DROP TABLE IF EXISTS items;
+DROP TABLE IF EXISTS items_fts;
+CREATE TABLE items (
+    rowid INTEGER PRIMARY KEY,
+    entry TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    record TEXT NOT NULL
+);
+CREATE INDEX items_entry ON items (entry, position);
+CREATE VIRTUAL TABLE items_fts USING fts5(
+    title, description, labels, people,
+    tokenize = 'trigram'
+);
+"#;
+
+fn open() -> Result<Connection> {
+    let conn = Connection::open(cache::DIR.join("cache.sqlite"))?;
+    // The updaters write while the workflow reads.
+    conn.busy_timeout(Duration::from_secs(5))?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version != SCHEMA_VERSION {
+        conn.execute_batch(SCHEMA)?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    }
+    Ok(conn)
+}
+
+/// Replaces the items stored for the cache entry.
+pub fn replace(entry: &str, items: &[json::Value]) -> Result<()> {
+    let mut conn = open()?;
+    let tx = conn.transaction()?;
+    delete(&tx, entry)?;
+    {
+        let mut insert =
+            tx.prepare("INSERT INTO items (entry, position, record) VALUES (?1, ?2, ?3)")?;
+        let mut index = tx.prepare(
+            "INSERT INTO items_fts (rowid, title, description, labels, people) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for (position, item) in items.iter().enumerate() {
+            let rowid = insert.insert(params![entry, position as i64, item.to_string()])?;
+            index.execute(params![
+                rowid,
+                text(item, "/title"),
+                text(item, "/description"),
+                labels(item),
+                people(item),
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Returns every item stored for the cache entry.
+pub fn items(entry: &str) -> Result<Vec<json::Value>> {
+    let conn = open()?;
+    parse(all(&conn, entry)?)
+}
+
+/// Returns the items stored for the cache entry that could match the query,
+/// in the order they were stored.
+pub fn search<T: DeserializeOwned>(entry: &str, query: &Query) -> Result<Vec<T>> {
+    let conn = open()?;
+    let records = match to_match(query) {
+        Some(query) => conn
+            .prepare(
+                "SELECT i.record FROM items i JOIN items_fts f ON f.rowid = i.rowid \
+                 WHERE i.entry = ?1 AND items_fts MATCH ?2 ORDER BY i.position",
+            )?
+            .query_map(params![entry, query], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?,
+        None => all(&conn, entry)?,
+    };
+    parse(records)
+}
+
+fn all(conn: &Connection, entry: &str) -> Result<Vec<String>> {
+    let records = conn
+        .prepare("SELECT record FROM items WHERE entry = ?1 ORDER BY position")?
+        .query_map(params![entry], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(records)
+}
+
+fn parse<T: DeserializeOwned>(records: Vec<String>) -> Result<Vec<T>> {
+    records.iter().map(|r| Ok(json::from_str(r)?)).collect()
+}
+
+/// Removes the items stored for the cache entry.
+pub fn remove(entry: &str) -> Result<()> {
+    let conn = open()?;
+    delete(&conn, entry)
+}
+
+/// Removes every stored item.
+pub fn clear() -> Result<()> {
+    let conn = open()?;
+    conn.execute_batch("DELETE FROM items_fts; DELETE FROM items;")?;
+    Ok(())
+}
+
+/// Returns the space used in the database by each cache entry. The size of
+/// the database files is shared between the entries in proportion to the
+/// size of their records, so that the sizes add up to the space used on disk.
+pub fn sizes() -> Result<HashMap<String, u64>> {
+    let conn = open()?;
+    let records: Vec<(String, u64)> = conn
+        .prepare("SELECT entry, SUM(LENGTH(record)) FROM items GROUP BY entry")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let total: u64 = records.iter().map(|(_, len)| len).sum();
+    let size: u64 = ["cache.sqlite", "cache.sqlite-wal"]
+        .iter()
+        .filter_map(|name| fs::metadata(cache::DIR.join(name)).ok())
+        .map(|m| m.len())
+        .sum();
+    Ok(records
+        .into_iter()
+        .map(|(entry, len)| (entry, size * len / total.max(1)))
+        .collect())
+}
+
+/// Rebuilds the database so that the space used by removed items is given
+/// back, otherwise the files never shrink.
+pub fn vacuum() -> Result<()> {
+    let conn = open()?;
+    conn.execute("VACUUM", [])?;
+    // The checkpoint returns a row with its results.
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    Ok(())
+}
+
+fn delete(conn: &Connection, entry: &str) -> Result<()> {
+    conn.execute(
+        "DELETE FROM items_fts WHERE rowid IN (SELECT rowid FROM items WHERE entry = ?1)",
+        params![entry],
+    )?;
+    conn.execute("DELETE FROM items WHERE entry = ?1", params![entry])?;
+    Ok(())
+}
+
+/// Converts a search query into an FTS query. The index is made of trigrams
+/// so that it matches substrings the same way the matcher does: every phrase
+/// must be in the title or description, `~label` in a label, and `@user` in
+/// the name or username of the author, an assignee or a reviewer. Words are
+/// left to the matcher since they match titles with typos in them, as are
+/// terms shorter than a trigram and the other terms.
+fn to_match(query: &Query) -> Option<String> {
+    let terms: Vec<_> = query
+        .terms
+        .iter()
+        .filter(|t| !t.negated)
+        .filter_map(|t| {
+            let (column, q) = match &t.filter {
+                Filter::Phrase(q) => ("{title description}", q),
+                Filter::Label(q) => ("labels", q),
+                Filter::User(q) => ("people", q),
+                _ => return None,
+            };
+            (q.chars().count() >= 3).then(|| format!("{column} : \"{}\"", q.replace('"', "\"\"")))
+        })
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" AND "))
+}
+
+fn text(item: &json::Value, ptr: &str) -> String {
+    item.pointer(ptr)
+        .and_then(json::Value::as_str)
+        .unwrap_or_default()
+        .to_owned()
+}
+
+fn labels(item: &json::Value) -> String {
+    item.get("labels")
+        .and_then(json::Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(json::Value::as_str)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn people(item: &json::Value) -> String {
+    let users = ["assignees", "reviewers"]
+        .iter()
+        .filter_map(|field| item.get(field)?.as_array())
+        .flatten()
+        .chain(item.get("author"));
+    users
+        .flat_map(|u| [text(u, "/name"), text(u, "/username")])
+        .collect::<Vec<_>>()
+        .join(" ")
+}